@@ -5,6 +5,7 @@
 use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem};
 use std::sync::mpsc::channel;
 
+#[allow(clippy::const_is_empty)]
 fn main() {
     let pty_system = NativePtySystem::default();
 
@@ -0,0 +1,390 @@
+//! Recording of pty sessions in the asciicast v2 format.
+//!
+//! `RecordingMasterPty` wraps a `MasterPty` so that output read from
+//! it, input written to it and changes to its size are appended as
+//! timestamped events to an asciicast v2 file, which can be played
+//! back with `asciinema play` and similar tools.
+//! See <https://docs.asciinema.org/manual/asciicast/v2/> for a
+//! description of the format.
+//! With the `asciicast` feature, `read_asciicast` reads such a file
+//! back, for example for playback with `replay::ReplayPtySystem`.
+//!
+//! ```no_run
+//! use portable_pty::asciicast::{Header, RecordingMasterPty};
+//! use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtyPair, PtySize};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let size = PtySize::default();
+//! let PtyPair { master, slave } = native_pty_system().openpty(size)?;
+//! let cmd = CommandBuilder::new_default_prog();
+//! let header = Header::from_command(size, &cmd);
+//! let mut child = slave.spawn_command(cmd)?;
+//! let master = RecordingMasterPty::new(master, header, std::fs::File::create("session.cast")?)?;
+//! let mut reader = master.try_clone_reader()?;
+//! std::io::copy(&mut reader, &mut std::io::sink())?;
+//! child.wait()?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::utf8::Utf8Decoder;
+use crate::{CommandBuilder, MasterPty, PtySize};
+use anyhow::Error;
+#[cfg(feature = "asciicast")]
+use anyhow::{Context as _, anyhow, bail};
+#[cfg(feature = "asciicast")]
+use serde_json::Value;
+#[cfg(feature = "asciicast")]
+use std::io::BufRead;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "asciicast")]
+use std::time::Duration;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The environment variables that `Header::from_command` records, as
+/// suggested by the asciicast specification
+pub const DEFAULT_ENV_KEYS: &[&str] = &["SHELL", "TERM"];
+
+/// The header line of an asciicast v2 file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// The number of columns of the terminal
+    pub width: u16,
+    /// The number of rows of the terminal
+    pub height: u16,
+    /// When the recording started, in seconds since the unix epoch
+    pub timestamp: Option<u64>,
+    /// The command that was recorded
+    pub command: Option<String>,
+    pub title: Option<String>,
+    /// A subset of the environment of the recorded command
+    pub env: Vec<(String, String)>,
+}
+
+impl Header {
+    /// Create a header for a terminal of the specified size, starting now
+    pub fn new(size: PtySize) -> Self {
+        Self {
+            width: size.cols,
+            height: size.rows,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+            command: None,
+            title: None,
+            env: vec![],
+        }
+    }
+
+    /// Create a header describing `cmd` running in a terminal of the
+    /// specified size.  The environment variables listed in
+    /// `DEFAULT_ENV_KEYS` are recorded if they are set for `cmd`.
+    pub fn from_command(size: PtySize, cmd: &CommandBuilder) -> Self {
+        let mut header = Self::new(size);
+        header.command = cmd.as_unix_command_line().ok();
+        header.env = DEFAULT_ENV_KEYS
+            .iter()
+            .filter_map(|key| {
+                let value = cmd.get_env(key)?.to_str()?;
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect();
+        header
+    }
+
+    /// Render the header as a line of JSON, without a trailing newline
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"version\": 2, \"width\": {}, \"height\": {}",
+            self.width, self.height
+        );
+        if let Some(timestamp) = self.timestamp {
+            json.push_str(&format!(", \"timestamp\": {timestamp}"));
+        }
+        if let Some(command) = &self.command {
+            json.push_str(&format!(", \"command\": {}", json_string(command)));
+        }
+        if let Some(title) = &self.title {
+            json.push_str(&format!(", \"title\": {}", json_string(title)));
+        }
+        if !self.env.is_empty() {
+            let env: Vec<String> = self
+                .env
+                .iter()
+                .map(|(key, value)| format!("{}: {}", json_string(key), json_string(value)))
+                .collect();
+            json.push_str(&format!(", \"env\": {{{}}}", env.join(", ")));
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Quote `s` as a JSON string
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{8}' => quoted.push_str("\\b"),
+            '\u{c}' => quoted.push_str("\\f"),
+            c if c < ' ' || c == '\u{7f}' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The destination of the events, shared between the master and the
+/// readers and writers obtained from it
+struct Sink {
+    output: Box<dyn Write + Send>,
+    start: Instant,
+}
+
+impl Sink {
+    fn event(&mut self, code: &str, data: &str) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let line = format!("[{elapsed:.6}, \"{code}\", {}]\n", json_string(data));
+        // Failing to record shouldn't disrupt the session itself
+        if let Err(err) = self
+            .output
+            .write_all(line.as_bytes())
+            .and_then(|_| self.output.flush())
+        {
+            log::error!("failed to write asciicast event: {err:#}");
+        }
+    }
+}
+
+/// A `MasterPty` that records the session in asciicast v2 format.
+///
+/// Output is recorded as it is read from readers obtained via
+/// `try_clone_reader`, so only one of them should be read from if
+/// the output isn't to be recorded more than once.
+pub struct RecordingMasterPty {
+    inner: Box<dyn MasterPty + Send>,
+    sink: Arc<Mutex<Sink>>,
+}
+
+impl RecordingMasterPty {
+    /// Wrap `inner`, writing `header` followed by the events of the
+    /// session to `output`.  Event times are relative to this call.
+    pub fn new(
+        inner: Box<dyn MasterPty + Send>,
+        header: Header,
+        mut output: impl Write + Send + 'static,
+    ) -> Result<Self, Error> {
+        writeln!(output, "{}", header.to_json())?;
+        output.flush()?;
+        Ok(Self {
+            inner,
+            sink: Arc::new(Mutex::new(Sink {
+                output: Box::new(output),
+                start: Instant::now(),
+            })),
+        })
+    }
+
+    /// Returns the wrapped master
+    pub fn inner(&self) -> &dyn MasterPty {
+        &*self.inner
+    }
+}
+
+impl MasterPty for RecordingMasterPty {
+    fn resize(&self, size: PtySize) -> Result<(), Error> {
+        self.inner.resize(size)?;
+        self.sink
+            .lock()
+            .unwrap()
+            .event("r", &format!("{}x{}", size.cols, size.rows));
+        Ok(())
+    }
+
+    fn get_size(&self) -> Result<PtySize, Error> {
+        self.inner.get_size()
+    }
+
+    fn try_clone_reader(&self) -> Result<Box<dyn Read + Send>, Error> {
+        Ok(Box::new(RecordingReader {
+            inner: self.inner.try_clone_reader()?,
+            sink: Arc::clone(&self.sink),
+            decoder: Utf8Decoder::default(),
+        }))
+    }
+
+    fn take_writer(&self) -> Result<Box<dyn Write + Send>, Error> {
+        Ok(Box::new(RecordingWriter {
+            inner: self.inner.take_writer()?,
+            sink: Arc::clone(&self.sink),
+            decoder: Utf8Decoder::default(),
+        }))
+    }
+
+    #[cfg(unix)]
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        self.inner.process_group_leader()
+    }
+
+    #[cfg(unix)]
+    fn as_raw_fd(&self) -> Option<crate::unix::RawFd> {
+        self.inner.as_raw_fd()
+    }
+
+    #[cfg(unix)]
+    fn tty_name(&self) -> Option<std::path::PathBuf> {
+        self.inner.tty_name()
+    }
+
+    #[cfg(unix)]
+    fn get_termios(&self) -> Option<nix::sys::termios::Termios> {
+        self.inner.get_termios()
+    }
+}
+
+/// Records output from the pty as "o" events
+struct RecordingReader {
+    inner: Box<dyn Read + Send>,
+    sink: Arc<Mutex<Sink>>,
+    decoder: Utf8Decoder,
+}
+
+impl Read for RecordingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        let text = self.decoder.decode(&buf[..size]);
+        if !text.is_empty() {
+            self.sink.lock().unwrap().event("o", &text);
+        }
+        Ok(size)
+    }
+}
+
+/// Records input to the pty as "i" events
+struct RecordingWriter {
+    inner: Box<dyn Write + Send>,
+    sink: Arc<Mutex<Sink>>,
+    decoder: Utf8Decoder,
+}
+
+impl Write for RecordingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.inner.write(buf)?;
+        let text = self.decoder.decode(&buf[..size]);
+        if !text.is_empty() {
+            self.sink.lock().unwrap().event("i", &text);
+        }
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An event read from an asciicast v2 file by `read_asciicast`
+#[cfg(feature = "asciicast")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The time of the event, relative to the start of the recording
+    pub time: Duration,
+    /// The type of the event: "o" for output, "i" for input, "r" for
+    /// a resize, "m" for a marker, and so on
+    pub code: String,
+    pub data: String,
+}
+
+/// Read an asciicast v2 file, returning its header and events.
+/// This requires the `asciicast` feature.
+#[cfg(feature = "asciicast")]
+pub fn read_asciicast(reader: impl BufRead) -> Result<(Header, Vec<Event>), Error> {
+    let mut lines = reader.lines().enumerate();
+    let header = loop {
+        let Some((idx, line)) = lines.next() else {
+            bail!("asciicast has no header");
+        };
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        break parse_header(&line).with_context(|| format!("line {}", idx + 1))?;
+    };
+
+    let mut events = vec![];
+    for (idx, line) in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        events.push(parse_event(&line).with_context(|| format!("line {}", idx + 1))?);
+    }
+    Ok((header, events))
+}
+
+#[cfg(feature = "asciicast")]
+fn parse_header(line: &str) -> Result<Header, Error> {
+    let Value::Object(fields) = serde_json::from_str(line)? else {
+        bail!("header is not an object");
+    };
+    match fields.get("version").and_then(Value::as_u64) {
+        Some(2) => {}
+        _ => bail!("unsupported asciicast version {:?}", fields.get("version")),
+    }
+    let dimension = |name: &str| {
+        fields
+            .get(name)
+            .and_then(Value::as_u64)
+            .and_then(|n| u16::try_from(n).ok())
+            .ok_or_else(|| anyhow!("header has no valid {name}"))
+    };
+    let string = |name: &str| fields.get(name).and_then(Value::as_str).map(str::to_string);
+    let env = match fields.get("env") {
+        Some(Value::Object(env)) => env
+            .iter()
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+            .collect(),
+        _ => vec![],
+    };
+    Ok(Header {
+        width: dimension("width")?,
+        height: dimension("height")?,
+        timestamp: fields.get("timestamp").and_then(Value::as_u64),
+        command: string("command"),
+        title: string("title"),
+        env,
+    })
+}
+
+#[cfg(feature = "asciicast")]
+fn parse_event(line: &str) -> Result<Event, Error> {
+    let Value::Array(items) = serde_json::from_str(line)? else {
+        bail!("event is not an array");
+    };
+    match items.as_slice() {
+        [
+            Value::Number(time),
+            Value::String(code),
+            Value::String(data),
+            ..,
+        ] => {
+            let time = time
+                .as_f64()
+                .and_then(|time| Duration::try_from_secs_f64(time).ok())
+                .ok_or_else(|| anyhow!("invalid event time {time}"))?;
+            Ok(Event {
+                time,
+                code: code.clone(),
+                data: data.clone(),
+            })
+        }
+        _ => bail!("malformed event"),
+    }
+}
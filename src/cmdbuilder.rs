@@ -39,6 +39,97 @@ impl EnvEntry {
     }
 }
 
+/// The identity that a child switches to before exec; see
+/// `CommandBuilder::credentials`
+#[cfg(unix)]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Credentials {
+    pub uid: Option<libc::uid_t>,
+    pub gid: Option<libc::gid_t>,
+    pub groups: Option<Vec<libc::gid_t>>,
+}
+
+/// A function run in the child before exec; see `CommandBuilder::pre_exec`
+#[cfg(unix)]
+pub(crate) type PreExecHook = std::sync::Arc<dyn Fn() -> std::io::Result<()> + Send + Sync>;
+
+/// The hooks added with `CommandBuilder::pre_exec`.  Closures can't be
+/// compared or printed, so hooks are compared by identity and only
+/// counted when printed.
+#[cfg(unix)]
+#[derive(Clone, Default)]
+struct PreExecHooks(Vec<PreExecHook>);
+
+#[cfg(unix)]
+impl std::fmt::Debug for PreExecHooks {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "[{} pre_exec hooks]", self.0.len())
+    }
+}
+
+#[cfg(unix)]
+impl PartialEq for PreExecHooks {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| std::sync::Arc::ptr_eq(a, b))
+    }
+}
+
+/// Returns the groups that `user` belongs to, including `gid`, as
+/// used by `initgroups(3)`
+#[cfg(unix)]
+fn group_list(user: &str, gid: libc::gid_t) -> anyhow::Result<Vec<libc::gid_t>> {
+    let name = std::ffi::CString::new(user)
+        .with_context(|| format!("invalid user name {user:?}"))?;
+    let mut groups: Vec<libc::gid_t> = vec![0; 64];
+    loop {
+        let mut count = groups.len() as libc::c_int;
+        // Some systems declare the groups as c_int rather than gid_t,
+        // which has the same size
+        let result = unsafe {
+            libc::getgrouplist(
+                name.as_ptr(),
+                gid as _,
+                groups.as_mut_ptr() as *mut _,
+                &mut count,
+            )
+        };
+        if result != -1 {
+            groups.truncate(count as usize);
+            return Ok(groups);
+        }
+        // The list didn't fit; some systems report the required size
+        let needed = (count as usize).max(groups.len() * 2);
+        if needed > 65536 {
+            anyhow::bail!("user {user:?} is a member of too many groups");
+        }
+        groups.resize(needed, 0);
+    }
+}
+
+/// Returns the shell of `user`, falling back to /bin/sh in the same
+/// way as `get_shell`
+#[cfg(unix)]
+fn user_shell(user: &nix::unistd::User) -> String {
+    use nix::unistd::{access, AccessFlags};
+
+    match user.shell.to_str() {
+        Some(shell) if access(shell, AccessFlags::X_OK).is_ok() => shell.to_owned(),
+        _ => {
+            log::warn!(
+                "passwd database shell={:?} for {} is not usable, falling back to /bin/sh",
+                user.shell,
+                user.name
+            );
+            "/bin/sh".into()
+        }
+    }
+}
+
 #[cfg(unix)]
 fn get_shell() -> String {
     use nix::unistd::{access, AccessFlags};
@@ -207,6 +298,30 @@ pub struct CommandBuilder {
     #[cfg(unix)]
     pub(crate) umask: Option<libc::mode_t>,
     controlling_tty: bool,
+    #[cfg(unix)]
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    kill_policy: crate::unix::KillPolicy,
+    #[cfg(unix)]
+    uid: Option<libc::uid_t>,
+    #[cfg(unix)]
+    gid: Option<libc::gid_t>,
+    #[cfg(unix)]
+    groups: Option<Vec<libc::gid_t>>,
+    #[cfg(unix)]
+    init_groups: Option<String>,
+    #[cfg(unix)]
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    rlimits: Vec<crate::unix::ResourceLimit>,
+    /// fd numbers only mean something within the process that owns
+    /// them, so these aren't serialized
+    #[cfg(unix)]
+    #[cfg_attr(feature = "serde_support", serde(skip))]
+    inherited_fds: Vec<(std::os::unix::io::RawFd, std::os::unix::io::RawFd)>,
+    /// Closures can't be serialized, so a deserialized builder has no
+    /// hooks
+    #[cfg(unix)]
+    #[cfg_attr(feature = "serde_support", serde(skip))]
+    pre_exec_hooks: PreExecHooks,
 }
 
 impl CommandBuilder {
@@ -220,6 +335,22 @@ impl CommandBuilder {
             #[cfg(unix)]
             umask: None,
             controlling_tty: true,
+            #[cfg(unix)]
+            kill_policy: Default::default(),
+            #[cfg(unix)]
+            uid: None,
+            #[cfg(unix)]
+            gid: None,
+            #[cfg(unix)]
+            groups: None,
+            #[cfg(unix)]
+            init_groups: None,
+            #[cfg(unix)]
+            rlimits: vec![],
+            #[cfg(unix)]
+            inherited_fds: vec![],
+            #[cfg(unix)]
+            pre_exec_hooks: Default::default(),
         }
     }
 
@@ -232,6 +363,22 @@ impl CommandBuilder {
             #[cfg(unix)]
             umask: None,
             controlling_tty: true,
+            #[cfg(unix)]
+            kill_policy: Default::default(),
+            #[cfg(unix)]
+            uid: None,
+            #[cfg(unix)]
+            gid: None,
+            #[cfg(unix)]
+            groups: None,
+            #[cfg(unix)]
+            init_groups: None,
+            #[cfg(unix)]
+            rlimits: vec![],
+            #[cfg(unix)]
+            inherited_fds: vec![],
+            #[cfg(unix)]
+            pre_exec_hooks: Default::default(),
         }
     }
 
@@ -259,6 +406,22 @@ impl CommandBuilder {
             #[cfg(unix)]
             umask: None,
             controlling_tty: true,
+            #[cfg(unix)]
+            kill_policy: Default::default(),
+            #[cfg(unix)]
+            uid: None,
+            #[cfg(unix)]
+            gid: None,
+            #[cfg(unix)]
+            groups: None,
+            #[cfg(unix)]
+            init_groups: None,
+            #[cfg(unix)]
+            rlimits: vec![],
+            #[cfg(unix)]
+            inherited_fds: vec![],
+            #[cfg(unix)]
+            pre_exec_hooks: Default::default(),
         }
     }
 
@@ -425,6 +588,211 @@ impl CommandBuilder {
         self.umask = mask;
     }
 
+    /// Set the policy used by `ChildKiller::kill` to terminate the
+    /// spawned child.  The policy is also carried over to any killers
+    /// obtained via `ChildKiller::clone_killer`.
+    pub fn set_kill_policy(&mut self, policy: crate::unix::KillPolicy) {
+        self.kill_policy = policy;
+    }
+
+    /// Returns the policy set with `set_kill_policy`
+    pub fn get_kill_policy(&self) -> &crate::unix::KillPolicy {
+        &self.kill_policy
+    }
+
+    /// Run the command as the user with the specified uid.
+    /// Unless overridden with `env`, HOME, SHELL, USER and LOGNAME are
+    /// taken from the password database entry for that user, and the
+    /// working directory defaults to their home directory.
+    /// If no `gid` is set, the primary group of the user is used, and
+    /// if neither `groups` nor `init_groups` is set, the supplementary
+    /// groups are reduced to that group alone.
+    /// Changing user generally requires privileges.
+    pub fn uid(&mut self, uid: Option<libc::uid_t>) {
+        self.uid = uid;
+    }
+
+    /// Returns the uid set with `uid`
+    pub fn get_uid(&self) -> Option<libc::uid_t> {
+        self.uid
+    }
+
+    /// Run the command with the specified group id
+    pub fn gid(&mut self, gid: Option<libc::gid_t>) {
+        self.gid = gid;
+    }
+
+    /// Returns the gid set with `gid`
+    pub fn get_gid(&self) -> Option<libc::gid_t> {
+        self.gid
+    }
+
+    /// Set the supplementary groups of the command.
+    /// This takes precedence over `init_groups`.
+    pub fn groups(&mut self, groups: Option<Vec<libc::gid_t>>) {
+        self.groups = groups;
+    }
+
+    /// Returns the supplementary groups set with `groups`
+    pub fn get_groups(&self) -> Option<&[libc::gid_t]> {
+        self.groups.as_deref()
+    }
+
+    /// Set the supplementary groups of the command to those that `user`
+    /// is a member of according to the group database, in the manner
+    /// of `initgroups(3)`.  The database is consulted before spawning.
+    pub fn init_groups(&mut self, user: Option<String>) {
+        self.init_groups = user;
+    }
+
+    /// Returns the user set with `init_groups`
+    pub fn get_init_groups(&self) -> Option<&str> {
+        self.init_groups.as_deref()
+    }
+
+    /// Limit the consumption of `resource` by the command, as with
+    /// `setrlimit(2)`; use `unix::RLIM_INFINITY` for no limit.
+    /// Setting a limit for a resource again replaces the earlier one.
+    /// Spawning fails if the soft limit exceeds the hard limit, or if
+    /// the hard limit would have to be raised without privileges.
+    pub fn rlimit(&mut self, resource: crate::unix::Resource, soft: u64, hard: u64) {
+        let limit = crate::unix::ResourceLimit {
+            resource,
+            soft,
+            hard,
+        };
+        match self.rlimits.iter_mut().find(|l| l.resource == resource) {
+            Some(existing) => *existing = limit,
+            None => self.rlimits.push(limit),
+        }
+    }
+
+    /// Returns the limits set with `rlimit`, in the order in which
+    /// their resources were first limited
+    pub fn get_rlimits(&self) -> &[crate::unix::ResourceLimit] {
+        &self.rlimits
+    }
+
+    /// Pass `parent_fd` to the command as `child_fd`.
+    /// Other than the stdio streams, which are connected to the pty,
+    /// descriptors are normally closed in the child; this exempts
+    /// `child_fd`, for example to pass a listening socket or a pipe.
+    /// `parent_fd` must remain open until the command has been spawned.
+    /// Using 0, 1 or 2 as `child_fd` replaces that stdio stream.
+    /// `parent_fd` may also be 0, 1 or 2, in which case the parent's
+    /// stdio stream is passed on rather than the pty.
+    /// Inherited fds are not serialized.
+    pub fn inherit_fd(
+        &mut self,
+        parent_fd: std::os::unix::io::RawFd,
+        child_fd: std::os::unix::io::RawFd,
+    ) {
+        self.inherited_fds.retain(|&(_, fd)| fd != child_fd);
+        self.inherited_fds.push((parent_fd, child_fd));
+    }
+
+    /// Returns the (parent_fd, child_fd) pairs set with `inherit_fd`
+    pub fn get_inherited_fds(
+        &self,
+    ) -> &[(std::os::unix::io::RawFd, std::os::unix::io::RawFd)] {
+        &self.inherited_fds
+    }
+
+    /// Schedule `hook` to be run in the child after it has been set up
+    /// and before the program is executed.  Hooks run in the order in
+    /// which they were added, after everything that the builder itself
+    /// configures: the session, the controlling terminal, inherited fds,
+    /// the umask, resource limits, and finally the uid and gid, so a
+    /// hook that needs privileges can't be combined with `uid`.
+    /// An error returned by a hook fails the spawn.
+    /// Hooks are not serialized.
+    ///
+    /// # Safety
+    ///
+    /// The hook runs in the child between fork and exec, where only
+    /// async-signal-safe operations are allowed: if the parent has other
+    /// threads, a hook that allocates or takes a lock can deadlock.
+    /// See `std::os::unix::process::CommandExt::pre_exec` for details.
+    pub unsafe fn pre_exec<F>(&mut self, hook: F)
+    where
+        F: Fn() -> std::io::Result<()> + Send + Sync + 'static,
+    {
+        self.pre_exec_hooks.0.push(std::sync::Arc::new(hook));
+    }
+
+    /// Returns the hooks added with `pre_exec`, in the order they run
+    pub(crate) fn get_pre_exec_hooks(&self) -> &[PreExecHook] {
+        &self.pre_exec_hooks.0
+    }
+
+    /// Looks up the password database entry of the user set with `uid`
+    fn target_user(&self) -> anyhow::Result<Option<nix::unistd::User>> {
+        match self.uid {
+            Some(uid) => nix::unistd::User::from_uid(uid.into())
+                .with_context(|| format!("looking up uid {uid} in the password database")),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the value of an environment variable only if it was
+    /// explicitly set on this builder, rather than inherited
+    fn get_explicit_env(&self, key: &str) -> Option<&OsStr> {
+        self.envs
+            .get(&EnvEntry::map_key(key.into()))
+            .filter(|entry| !entry.is_from_base_env)
+            .map(|entry| entry.value.as_os_str())
+    }
+
+    /// Resolves the credentials that the child should switch to
+    /// before exec, or None if it should run as the caller
+    pub(crate) fn credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        if self.uid.is_none()
+            && self.gid.is_none()
+            && self.groups.is_none()
+            && self.init_groups.is_none()
+        {
+            return Ok(None);
+        }
+
+        let user = self.target_user()?;
+        let gid = match (self.gid, &user) {
+            (Some(gid), _) => Some(gid),
+            (None, Some(user)) => Some(user.gid.as_raw()),
+            (None, None) => match self.uid {
+                Some(uid) => anyhow::bail!(
+                    "uid {uid} has no password database entry, so its group \
+                     cannot be determined; set a gid explicitly"
+                ),
+                None => None,
+            },
+        };
+
+        let groups = if let Some(groups) = &self.groups {
+            Some(groups.clone())
+        } else if let Some(name) = &self.init_groups {
+            let base = match gid {
+                Some(gid) => gid,
+                None => nix::unistd::User::from_name(name)
+                    .with_context(|| format!("looking up user {name:?}"))?
+                    .with_context(|| format!("user {name:?} not found"))?
+                    .gid
+                    .as_raw(),
+            };
+            Some(group_list(name, base)?)
+        } else if self.uid.is_some_and(|uid| uid != unsafe { libc::geteuid() }) {
+            // Don't let the new user keep our supplementary groups
+            gid.map(|gid| vec![gid])
+        } else {
+            None
+        };
+
+        Ok(Some(Credentials {
+            uid: self.uid,
+            gid,
+            groups,
+        }))
+    }
+
     fn resolve_path(&self) -> Option<&OsStr> {
         self.get_env("PATH")
     }
@@ -514,12 +882,29 @@ impl CommandBuilder {
     pub(crate) fn as_command(&self) -> anyhow::Result<std::process::Command> {
         use std::os::unix::process::CommandExt;
 
-        let home = self.get_home_dir()?;
+        let user = self.target_user()?;
+        let home = match &user {
+            Some(user) if self.get_explicit_env("HOME").is_none() => user
+                .dir
+                .to_str()
+                .map(str::to_owned)
+                .context("failed to resolve home dir")?,
+            _ => self.get_home_dir()?,
+        };
+        // Like login(1), start a user whose home is missing in /
+        let default_dir = if user.is_some() && !Path::new(&home).is_dir() {
+            "/"
+        } else {
+            home.as_str()
+        };
         let dir: &OsStr = self
             .cwd.as_deref()
             .filter(|dir| std::path::Path::new(dir).is_dir())
-            .unwrap_or(home.as_ref());
-        let shell = self.get_shell();
+            .unwrap_or(default_dir.as_ref());
+        let shell = match &user {
+            Some(user) if self.get_explicit_env("SHELL").is_none() => user_shell(user),
+            _ => self.get_shell(),
+        };
 
         let mut cmd = if self.is_default_prog() {
             let mut cmd = std::process::Command::new(&shell);
@@ -540,7 +925,7 @@ impl CommandBuilder {
         cmd.current_dir(dir);
 
         cmd.env_clear();
-        cmd.env("SHELL", shell);
+        cmd.env("SHELL", &shell);
         cmd.envs(self.envs.values().map(
             |EnvEntry {
                  is_from_base_env: _,
@@ -549,6 +934,22 @@ impl CommandBuilder {
              }| (preferred_key.as_os_str(), value.as_os_str()),
         ));
 
+        // Describe the user that the command runs as, rather than the
+        // caller, unless the caller explicitly asked otherwise
+        if let Some(user) = &user {
+            if self.get_explicit_env("HOME").is_none() {
+                cmd.env("HOME", &home);
+            }
+            if self.get_explicit_env("SHELL").is_none() {
+                cmd.env("SHELL", &shell);
+            }
+            for key in ["USER", "LOGNAME"] {
+                if self.get_explicit_env(key).is_none() {
+                    cmd.env(key, &user.name);
+                }
+            }
+        }
+
         Ok(cmd)
     }
 
@@ -0,0 +1,374 @@
+//! Expect-style automation of programs running in a pty.
+//!
+//! A `Session` owns the master end of a pty and the child process
+//! running in it.  Output from the child is collected by a reader
+//! thread, and `Session::expect` waits until that output matches a
+//! `Pattern`, allowing interactive programs such as installers and
+//! REPLs to be driven in a script-like fashion:
+//!
+//! ```no_run
+//! use portable_pty::expect::{Pattern, Session};
+//! use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let pair = native_pty_system().openpty(PtySize::default())?;
+//! let mut session = Session::spawn(pair, CommandBuilder::new("python3"))?;
+//! session.expect(">>> ")?;
+//! session.send_line("print(6 * 7)")?;
+//! let found = session.expect(Pattern::regex(r"(\d+)\r?\n")?)?;
+//! assert_eq!(found.captures[1].as_deref(), Some("42"));
+//! # Ok(())
+//! # }
+//! ```
+use crate::{Child, CommandBuilder, ExitStatus, MasterPty, PtyPair};
+use crate::utf8::Utf8Decoder;
+use anyhow::{Context as _, bail};
+use regex::Regex;
+use std::io::{Read, Write};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::time::{Duration, Instant};
+
+/// The line ending appended by `Session::send_line`
+#[cfg(windows)]
+pub const LINE_ENDING: &str = "\r\n";
+#[cfg(not(windows))]
+pub const LINE_ENDING: &str = "\n";
+
+/// How long `Session::expect` waits for a match unless changed
+/// with `Session::set_timeout`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something to wait for in the output of the child
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Matches the earliest occurrence of the regular expression
+    Regex(Regex),
+    /// Matches the earliest occurrence of the literal text
+    Literal(String),
+    /// Matches once the child has closed the pty and all of its
+    /// output has been consumed
+    Eof,
+    /// Matches when the timeout elapses without any other pattern
+    /// matching.  Without this pattern, a timeout is an error.
+    Timeout,
+}
+
+impl Pattern {
+    /// Construct a pattern from a regular expression
+    pub fn regex(re: &str) -> anyhow::Result<Self> {
+        Ok(Self::Regex(Regex::new(re)?))
+    }
+
+    /// Construct a pattern matching the literal text `text`
+    pub fn literal(text: impl Into<String>) -> Self {
+        Self::Literal(text.into())
+    }
+
+    /// Returns the start and end of the earliest match in `text`,
+    /// along with the capture groups for a regex.
+    fn find(&self, text: &str) -> Option<(usize, usize, Vec<Option<String>>)> {
+        match self {
+            Self::Regex(re) => {
+                let caps = re.captures(text)?;
+                let whole = caps.get(0)?;
+                let captures = caps
+                    .iter()
+                    .map(|m| m.map(|m| m.as_str().to_string()))
+                    .collect();
+                Some((whole.start(), whole.end(), captures))
+            }
+            Self::Literal(literal) => {
+                let start = text.find(literal.as_str())?;
+                let end = start + literal.len();
+                Some((start, end, vec![Some(literal.clone())]))
+            }
+            Self::Eof | Self::Timeout => None,
+        }
+    }
+}
+
+impl From<Regex> for Pattern {
+    fn from(re: Regex) -> Self {
+        Self::Regex(re)
+    }
+}
+
+impl From<&str> for Pattern {
+    fn from(text: &str) -> Self {
+        Self::literal(text)
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(text: String) -> Self {
+        Self::Literal(text)
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Regex(re) => write!(fmt, "regex {:?}", re.as_str()),
+            Self::Literal(text) => write!(fmt, "{:?}", text),
+            Self::Eof => write!(fmt, "EOF"),
+            Self::Timeout => write!(fmt, "timeout"),
+        }
+    }
+}
+
+/// The result of a successful `Session::expect`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// The index of the pattern that matched, for `expect_any`
+    pub index: usize,
+    /// The output that preceded the match.  For `Pattern::Eof` and
+    /// `Pattern::Timeout`, this is all of the unconsumed output.
+    pub before: String,
+    /// The text that matched the pattern.  This is empty for
+    /// `Pattern::Eof` and `Pattern::Timeout`.
+    pub after: String,
+    /// The capture groups of a `Pattern::Regex`, with the whole match
+    /// at index 0.  Literals have just the whole match.
+    pub captures: Vec<Option<String>>,
+}
+
+/// An entry in the transcript of a `Session`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptEntry {
+    /// Text that was sent to the child
+    Sent(String),
+    /// Text that was received from the child
+    Received(String),
+}
+
+enum ReaderEvent {
+    Data(String),
+    Eof,
+    Error(std::io::Error),
+}
+
+/// Drives a child process running in a pty.
+/// See the module documentation for an example.
+pub struct Session {
+    master: Box<dyn MasterPty + Send>,
+    child: Box<dyn Child + Send + Sync>,
+    writer: Box<dyn Write + Send>,
+    rx: Receiver<ReaderEvent>,
+    /// Output that has been received but not yet consumed by a match
+    buffer: String,
+    eof: bool,
+    timeout: Duration,
+    transcript: Vec<TranscriptEntry>,
+}
+
+impl Session {
+    /// Spawn `cmd` into the slave end of `pair` and start a session
+    /// driving it.  The slave is dropped once the command is spawned
+    /// so that EOF is seen once the child exits.
+    pub fn spawn(pair: PtyPair, cmd: CommandBuilder) -> anyhow::Result<Self> {
+        let PtyPair { master, slave } = pair;
+        let child = slave.spawn_command(cmd)?;
+        drop(slave);
+        Self::new(master, child)
+    }
+
+    /// Start a session driving `child`, which is running in the pty
+    /// whose master end is `master`.
+    pub fn new(
+        master: Box<dyn MasterPty + Send>,
+        child: Box<dyn Child + Send + Sync>,
+    ) -> anyhow::Result<Self> {
+        let reader = master.try_clone_reader()?;
+        let writer = master.take_writer()?;
+        let (tx, rx) = channel();
+        std::thread::Builder::new()
+            .name("portable-pty-expect".to_string())
+            .spawn(move || read_output(reader, tx))
+            .context("spawning expect reader thread")?;
+
+        Ok(Self {
+            master,
+            child,
+            writer,
+            rx,
+            buffer: String::new(),
+            eof: false,
+            timeout: DEFAULT_TIMEOUT,
+            transcript: vec![],
+        })
+    }
+
+    /// Set how long `expect` waits for a match
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Send `text` to the child
+    pub fn send(&mut self, text: &str) -> anyhow::Result<()> {
+        self.writer
+            .write_all(text.as_bytes())
+            .context("writing to pty")?;
+        self.writer.flush()?;
+        self.transcript
+            .push(TranscriptEntry::Sent(text.to_string()));
+        Ok(())
+    }
+
+    /// Send `line` to the child, followed by `LINE_ENDING`
+    pub fn send_line(&mut self, line: &str) -> anyhow::Result<()> {
+        self.send(&format!("{line}{LINE_ENDING}"))
+    }
+
+    /// Wait until the output of the child matches `pattern`, consuming
+    /// the output up to and including the match.
+    pub fn expect(&mut self, pattern: impl Into<Pattern>) -> anyhow::Result<Match> {
+        self.expect_any(&[pattern.into()])
+    }
+
+    /// Wait until the output of the child matches one of `patterns`.
+    /// When more than one pattern matches, the one that matches
+    /// earliest in the output wins, with ties going to the pattern
+    /// that is listed first.
+    /// Fails if the timeout elapses or EOF is reached without a match,
+    /// unless `Pattern::Timeout` or `Pattern::Eof` respectively is one
+    /// of the alternatives.
+    pub fn expect_any(&mut self, patterns: &[Pattern]) -> anyhow::Result<Match> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(found) = self.find(patterns) {
+                return Ok(found);
+            }
+
+            if self.eof {
+                if let Some(index) = patterns.iter().position(|p| matches!(p, Pattern::Eof)) {
+                    return Ok(self.consume_all(index));
+                }
+                bail!(
+                    "EOF while waiting for {}; unmatched output: {:?}",
+                    describe(patterns),
+                    self.buffer
+                );
+            }
+
+            let now = Instant::now();
+            let event = if now >= deadline {
+                Err(RecvTimeoutError::Timeout)
+            } else {
+                self.rx.recv_timeout(deadline - now)
+            };
+            match event {
+                Ok(ReaderEvent::Data(data)) => {
+                    self.buffer.push_str(&data);
+                    self.transcript.push(TranscriptEntry::Received(data));
+                }
+                Ok(ReaderEvent::Eof) | Err(RecvTimeoutError::Disconnected) => {
+                    self.eof = true;
+                }
+                Ok(ReaderEvent::Error(err)) => {
+                    self.eof = true;
+                    return Err(err).context("reading from pty");
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(index) = patterns.iter().position(|p| matches!(p, Pattern::Timeout))
+                    {
+                        return Ok(self.consume_all(index));
+                    }
+                    bail!(
+                        "timed out after {:?} waiting for {}; unmatched output: {:?}",
+                        self.timeout,
+                        describe(patterns),
+                        self.buffer
+                    );
+                }
+            }
+        }
+    }
+
+    fn find(&mut self, patterns: &[Pattern]) -> Option<Match> {
+        let (index, (start, end, captures)) = patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(index, pattern)| Some((index, pattern.find(&self.buffer)?)))
+            .min_by_key(|(index, (start, _, _))| (*start, *index))?;
+
+        let before = self.buffer[..start].to_string();
+        let after = self.buffer[start..end].to_string();
+        self.buffer.drain(..end);
+        Some(Match {
+            index,
+            before,
+            after,
+            captures,
+        })
+    }
+
+    fn consume_all(&mut self, index: usize) -> Match {
+        Match {
+            index,
+            before: std::mem::take(&mut self.buffer),
+            after: String::new(),
+            captures: vec![],
+        }
+    }
+
+    /// Returns the exchange with the child so far, in order
+    pub fn transcript(&self) -> &[TranscriptEntry] {
+        &self.transcript
+    }
+
+    /// Returns the output that has been received but not yet consumed
+    /// by a match
+    pub fn pending_output(&self) -> &str {
+        &self.buffer
+    }
+
+    pub fn master(&self) -> &dyn MasterPty {
+        &*self.master
+    }
+
+    pub fn child(&mut self) -> &mut (dyn Child + Send + Sync) {
+        &mut *self.child
+    }
+
+    /// Wait for the child to exit, yielding its exit status
+    pub fn wait(&mut self) -> anyhow::Result<ExitStatus> {
+        self.child.wait().context("waiting for child to exit")
+    }
+}
+
+fn describe(patterns: &[Pattern]) -> String {
+    patterns
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+/// Runs on the reader thread, forwarding output to the session until
+/// EOF or the session is dropped.
+fn read_output(mut reader: Box<dyn Read + Send>, tx: Sender<ReaderEvent>) {
+    let mut buf = [0u8; 4096];
+    let mut decoder = Utf8Decoder::default();
+    loop {
+        let event = match reader.read(&mut buf) {
+            Ok(0) => ReaderEvent::Eof,
+            Ok(n) => {
+                let text = decoder.decode(&buf[..n]);
+                if text.is_empty() {
+                    continue;
+                }
+                ReaderEvent::Data(text)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => ReaderEvent::Error(err),
+        };
+        let done = !matches!(event, ReaderEvent::Data(_));
+        if tx.send(event).is_err() || done {
+            break;
+        }
+    }
+}
@@ -39,13 +39,15 @@
 //!
 use anyhow::Error;
 use downcast_rs::{impl_downcast, Downcast};
-#[cfg(unix)]
 #[cfg(feature = "serde_support")]
 use serde::{Deserialize, Serialize};
 use std::io::Result as IoResult;
+use std::sync::{Condvar, MutexGuard};
+use std::time::{Duration, Instant};
 #[cfg(windows)]
 use std::os::windows::prelude::{AsRawHandle, RawHandle};
 
+pub mod asciicast;
 pub mod cmdbuilder;
 pub use cmdbuilder::CommandBuilder;
 
@@ -54,7 +56,20 @@ pub mod unix;
 #[cfg(windows)]
 pub mod win;
 
+pub mod replay;
+pub mod run;
 pub mod serial;
+pub mod session;
+mod utf8;
+
+#[cfg(feature = "screen")]
+pub mod screen;
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(feature = "expect")]
+pub mod expect;
 
 /// Represents the size of the visible display area in the pty
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -135,6 +150,25 @@ pub trait Child: std::fmt::Debug + ChildKiller + Downcast + Send {
     /// Blocks execution until the child process has completed,
     /// yielding its exit status.
     fn wait(&mut self) -> IoResult<ExitStatus>;
+    /// Blocks execution until the child process has completed or
+    /// `timeout` has elapsed.
+    /// Returns None if the child is still running after the timeout,
+    /// else returns its exit status.
+    /// A timeout too large to represent as a deadline waits indefinitely.
+    fn wait_timeout(&mut self, timeout: Duration) -> IoResult<Option<ExitStatus>> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.wait_deadline(deadline),
+            None => self.wait().map(Some),
+        }
+    }
+    /// Blocks execution until the child process has completed or
+    /// `deadline` has passed.
+    /// Returns None if the child is still running at the deadline,
+    /// else returns its exit status.
+    /// The default implementation polls `try_wait`.
+    fn wait_deadline(&mut self, deadline: Instant) -> IoResult<Option<ExitStatus>> {
+        poll_until_deadline(self, deadline)
+    }
     /// Returns the process identifier of the child process,
     /// if applicable
     fn process_id(&self) -> Option<u32>;
@@ -145,11 +179,94 @@ pub trait Child: std::fmt::Debug + ChildKiller + Downcast + Send {
 }
 impl_downcast!(Child);
 
+/// Waits for `child` to complete by polling `try_wait` until `deadline`,
+/// backing off gradually so that short lived children are noticed
+/// promptly without spinning on long lived ones.
+pub(crate) fn poll_until_deadline<C: Child + ?Sized>(
+    child: &mut C,
+    deadline: Instant,
+) -> IoResult<Option<ExitStatus>> {
+    let mut interval = Duration::from_millis(1);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(interval.min(deadline - now));
+        interval = (interval * 2).min(Duration::from_millis(50));
+    }
+}
+
+/// Waits on `changed` until `status` yields an exit status from the
+/// guarded state, giving up once `deadline`, if any, has passed.
+/// Shared by the in-process children of `mock` and `replay`.
+pub(crate) fn wait_for_status<S>(
+    mut state: MutexGuard<S>,
+    changed: &Condvar,
+    deadline: Option<Instant>,
+    status: impl Fn(&S) -> Option<&ExitStatus>,
+) -> Option<ExitStatus> {
+    loop {
+        if let Some(status) = status(&state) {
+            return Some(status.clone());
+        }
+        state = match deadline {
+            None => changed.wait(state).unwrap(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                changed.wait_timeout(state, deadline - now).unwrap().0
+            }
+        };
+    }
+}
+
 /// Represents the ability to signal a Child to terminate
 pub trait ChildKiller: std::fmt::Debug + Downcast + Send {
     /// Terminate the child process
     fn kill(&mut self) -> IoResult<()>;
 
+    /// Send an arbitrary signal to the child process.
+    /// Unlike `kill`, no attempt is made to wait for or escalate
+    /// beyond the requested signal, which makes this suitable for
+    /// things like SIGINT, SIGWINCH or SIGSTOP/SIGCONT.
+    /// The default implementation reports that signals are unsupported.
+    #[cfg(unix)]
+    fn signal(&mut self, signal: unix::Signal) -> IoResult<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("cannot deliver {signal} to this kind of child"),
+        ))
+    }
+
+    /// Send a signal to the processes in `scope` relative to the child.
+    /// The default implementation only supports `KillScope::Process`.
+    #[cfg(unix)]
+    fn signal_scoped(&mut self, signal: unix::Signal, scope: unix::KillScope) -> IoResult<()> {
+        match scope {
+            unix::KillScope::Process => self.signal(signal),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("cannot deliver {signal} to {scope:?} of this kind of child"),
+            )),
+        }
+    }
+
+    /// Terminate the child process by following the steps of `policy`
+    /// rather than the policy that the child was spawned with.
+    /// Implementations that have no way to deliver signals fall back
+    /// to `kill`.
+    #[cfg(unix)]
+    fn kill_with_policy(&mut self, policy: &unix::KillPolicy) -> IoResult<()> {
+        let _ = policy;
+        self.kill()
+    }
+
     /// Clone an object that can be split out from the Child in order
     /// to send it signals independently from a thread that may be
     /// blocked in `.wait`.
@@ -165,16 +282,33 @@ pub trait SlavePty {
 }
 
 /// Represents the exit status of a child process.
+///
+/// For compatibility, a process that was terminated by a signal reports
+/// an `exit_code` of 1; use `code` or `signal_number` to distinguish
+/// that from a process that exited with status 1.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
 pub struct ExitStatus {
     code: u32,
     signal: Option<String>,
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    signal_number: Option<i32>,
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    core_dumped: bool,
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    raw: Option<i32>,
 }
 
 impl ExitStatus {
     /// Construct an ExitStatus from a process return code
     pub fn with_exit_code(code: u32) -> Self {
-        Self { code, signal: None }
+        Self {
+            code,
+            signal: None,
+            signal_number: None,
+            core_dumped: false,
+            raw: None,
+        }
     }
 
     /// Construct an ExitStatus from a signal name
@@ -182,6 +316,9 @@ impl ExitStatus {
         Self {
             code: 1,
             signal: Some(signal.to_string()),
+            signal_number: None,
+            core_dumped: false,
+            raw: None,
         }
     }
 
@@ -198,10 +335,49 @@ impl ExitStatus {
         self.code
     }
 
+    /// Returns the exit code of the process, or None if it was
+    /// terminated by a signal
+    pub fn code(&self) -> Option<u32> {
+        match self.signal {
+            None => Some(self.code),
+            Some(_) => None,
+        }
+    }
+
     /// Returns the signal if present that this ExitStatus was constructed with
     pub fn signal(&self) -> Option<&str> {
         self.signal.as_deref()
     }
+
+    /// Returns the number of the signal that terminated the process,
+    /// if known
+    pub fn signal_number(&self) -> Option<i32> {
+        self.signal_number
+    }
+
+    /// Returns true if the process was terminated by a signal and
+    /// produced a core dump
+    pub fn core_dumped(&self) -> bool {
+        self.core_dumped
+    }
+
+    /// Returns the raw wait status as reported by the system, if this
+    /// ExitStatus was constructed from one
+    pub fn raw_status(&self) -> Option<i32> {
+        self.raw
+    }
+}
+
+/// Returns the human readable description of `signal`
+#[cfg(unix)]
+fn signal_description(signal: i32) -> String {
+    let signame = unsafe { libc::strsignal(signal) };
+    if signame.is_null() {
+        format!("Signal {}", signal)
+    } else {
+        let signame = unsafe { std::ffi::CStr::from_ptr(signame) };
+        signame.to_string_lossy().to_string()
+    }
 }
 
 impl From<std::process::ExitStatus> for ExitStatus {
@@ -211,17 +387,12 @@ impl From<std::process::ExitStatus> for ExitStatus {
             use std::os::unix::process::ExitStatusExt;
 
             if let Some(signal) = status.signal() {
-                let signame = unsafe { libc::strsignal(signal) };
-                let signal = if signame.is_null() {
-                    format!("Signal {}", signal)
-                } else {
-                    let signame = unsafe { std::ffi::CStr::from_ptr(signame) };
-                    signame.to_string_lossy().to_string()
-                };
-
                 return ExitStatus {
                     code: status.code().map(|c| c as u32).unwrap_or(1),
-                    signal: Some(signal),
+                    signal: Some(signal_description(signal)),
+                    signal_number: Some(signal),
+                    core_dumped: status.core_dumped(),
+                    raw: Some(status.into_raw()),
                 };
             }
         }
@@ -232,7 +403,21 @@ impl From<std::process::ExitStatus> for ExitStatus {
                 .map(|c| c as u32)
                 .unwrap_or_else(|| if status.success() { 0 } else { 1 });
 
-        ExitStatus { code, signal: None }
+        #[cfg(unix)]
+        let raw = {
+            use std::os::unix::process::ExitStatusExt;
+            Some(status.into_raw())
+        };
+        #[cfg(not(unix))]
+        let raw = None;
+
+        ExitStatus {
+            code,
+            signal: None,
+            signal_number: None,
+            core_dumped: false,
+            raw,
+        }
     }
 }
 
@@ -290,6 +475,14 @@ impl Child for std::process::Child {
 struct ProcessSignaller {
     pid: Option<u32>,
 
+    /// On Linux, a duplicate of the child's pidfd, so that we can't
+    /// signal an unrelated process that has reused the pid
+    #[cfg(unix)]
+    pidfd: Option<std::os::unix::io::OwnedFd>,
+
+    #[cfg(unix)]
+    kill_policy: unix::KillPolicy,
+
     #[cfg(windows)]
     handle: Option<filedescriptor::OwnedHandle>,
 }
@@ -316,11 +509,53 @@ impl ChildKiller for ProcessSignaller {
     }
 }
 
+#[cfg(unix)]
+impl ProcessSignaller {
+    fn has_exited(&self, pid: u32) -> bool {
+        match &self.pidfd {
+            Some(pidfd) => unix::pidfd::has_exited(pidfd),
+            // We're not the one responsible for reaping the child, so we
+            // check for termination without consuming its exit status.
+            None => unix::has_exited(pid),
+        }
+    }
+
+    fn is_reaped(&self, pid: u32) -> bool {
+        match &self.pidfd {
+            Some(pidfd) => unix::pidfd::is_reaped(pidfd),
+            None => unix::is_reaped(pid),
+        }
+    }
+}
+
 #[cfg(unix)]
 impl ChildKiller for ProcessSignaller {
     fn kill(&mut self) -> IoResult<()> {
+        let policy = self.kill_policy.clone();
+        self.kill_with_policy(&policy)
+    }
+
+    fn kill_with_policy(&mut self, policy: &unix::KillPolicy) -> IoResult<()> {
+        let Some(pid) = self.pid else {
+            return Ok(());
+        };
+        policy.apply(self, pid, |s| s.has_exited(pid))
+    }
+
+    fn signal_scoped(&mut self, signal: unix::Signal, scope: unix::KillScope) -> IoResult<()> {
+        match (self.pid, scope) {
+            (_, unix::KillScope::Process) => self.signal(signal),
+            (Some(pid), scope) => unix::signal_scope(pid, signal, scope, self.is_reaped(pid)),
+            (None, _) => Ok(()),
+        }
+    }
+
+    fn signal(&mut self, signal: unix::Signal) -> IoResult<()> {
+        if let Some(pidfd) = &self.pidfd {
+            return unix::pidfd::send_signal(pidfd, signal);
+        }
         if let Some(pid) = self.pid {
-            let result = unsafe { libc::kill(pid as i32, libc::SIGHUP) };
+            let result = unsafe { libc::kill(pid as i32, signal as libc::c_int) };
             if result != 0 {
                 return Err(std::io::Error::last_os_error());
             }
@@ -329,43 +564,56 @@ impl ChildKiller for ProcessSignaller {
     }
 
     fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
-        Box::new(Self { pid: self.pid })
+        Box::new(Self {
+            pid: self.pid,
+            pidfd: self.pidfd.as_ref().and_then(|fd| fd.try_clone().ok()),
+            kill_policy: self.kill_policy.clone(),
+        })
     }
 }
 
 impl ChildKiller for std::process::Child {
+    #[cfg(unix)]
     fn kill(&mut self) -> IoResult<()> {
-        #[cfg(unix)]
-        {
-            // On unix, we send the SIGHUP signal instead of trying to kill
-            // the process. The default behavior of a process receiving this
-            // signal is to be killed unless it configured a signal handler.
-            let result = unsafe { libc::kill(self.id() as i32, libc::SIGHUP) };
-            if result != 0 {
-                return Err(std::io::Error::last_os_error());
-            }
+        // On unix, we send the SIGHUP signal instead of trying to kill
+        // the process. The default behavior of a process receiving this
+        // signal is to be killed unless it configured a signal handler.
+        // Since SIGHUP doesn't guarantee termination, the default policy
+        // gives the process a bit of a grace period to shutdown or do
+        // whatever it is doing in its signal handler before we proceed
+        // with the full on kill.
+        self.kill_with_policy(&unix::KillPolicy::default())
+    }
 
-            // We successfully delivered SIGHUP, but the semantics of Child::kill
-            // are that on success the process is dead or shortly about to
-            // terminate.  Since SIGUP doesn't guarantee termination, we
-            // give the process a bit of a grace period to shutdown or do whatever
-            // it is doing in its signal handler befre we proceed with the
-            // full on kill.
-            for attempt in 0..5 {
-                if attempt > 0 {
-                    std::thread::sleep(std::time::Duration::from_millis(50));
-                }
+    #[cfg(windows)]
+    fn kill(&mut self) -> IoResult<()> {
+        std::process::Child::kill(self)
+    }
 
-                if let Ok(Some(_)) = self.try_wait() {
-                    // It completed, so report success!
-                    return Ok(());
-                }
-            }
+    #[cfg(unix)]
+    fn kill_with_policy(&mut self, policy: &unix::KillPolicy) -> IoResult<()> {
+        let pid = self.id();
+        policy.apply(self, pid, |child| matches!(child.try_wait(), Ok(Some(_))))
+    }
 
-            // it's still alive after a grace period, so proceed with a kill
+    #[cfg(unix)]
+    fn signal_scoped(&mut self, signal: unix::Signal, scope: unix::KillScope) -> IoResult<()> {
+        match scope {
+            unix::KillScope::Process => self.signal(signal),
+            scope => {
+                let reaped = self.try_wait()?.is_some();
+                unix::signal_scope(self.id(), signal, scope, reaped)
+            }
         }
+    }
 
-        std::process::Child::kill(self)
+    #[cfg(unix)]
+    fn signal(&mut self, signal: unix::Signal) -> IoResult<()> {
+        let result = unsafe { libc::kill(self.id() as i32, signal as libc::c_int) };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
     }
 
     #[cfg(windows)]
@@ -387,8 +635,11 @@ impl ChildKiller for std::process::Child {
 
     #[cfg(unix)]
     fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        let pid = self.id();
         Box::new(ProcessSignaller {
-            pid: self.process_id(),
+            pid: Some(pid),
+            pidfd: unix::pidfd::open(pid),
+            kill_policy: unix::KillPolicy::default(),
         })
     }
 }
@@ -0,0 +1,410 @@
+//! An in-memory `PtySystem` for unit testing code that consumes ptys.
+//!
+//! `MockPtySystem` hands out ptys that are backed by buffers rather
+//! than by the system.  Each pty has a `MockPty` handle through which
+//! a test can feed output to readers of the master, inspect the input
+//! written to it, observe calls to `resize`, see which commands were
+//! spawned, and decide when and how the spawned child exits.
+//!
+//! `MockPtySystem` is cheap to clone and clones share their ptys, so a
+//! test can keep a clone after handing the system to the code under test.
+//!
+//! ```
+//! use portable_pty::mock::MockPtySystem;
+//! use portable_pty::{CommandBuilder, ExitStatus, PtySize, PtySystem};
+//! use std::io::{Read, Write};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let pty_system = MockPtySystem::new();
+//! let pair = pty_system.openpty(PtySize::default())?;
+//! let mut child = pair.slave.spawn_command(CommandBuilder::new("bash"))?;
+//!
+//! let pty = pty_system.last_pty().unwrap();
+//! pty.write_output(b"$ ");
+//! pty.close_output();
+//! pair.master.take_writer()?.write_all(b"exit\n")?;
+//! pty.exit(ExitStatus::with_exit_code(0));
+//!
+//! let mut output = String::new();
+//! pair.master.try_clone_reader()?.read_to_string(&mut output)?;
+//! assert_eq!(output, "$ ");
+//! assert_eq!(pty.input(), b"exit\n");
+//! assert!(child.wait()?.success());
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtyPair, PtySize, PtySystem,
+    SlavePty, wait_for_status,
+};
+use anyhow::{Error, bail};
+use std::collections::VecDeque;
+use std::io::{Read, Result as IoResult, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// A `PtySystem` whose ptys are backed by memory.
+/// See the module documentation for an example.
+#[derive(Clone, Default)]
+pub struct MockPtySystem {
+    ptys: Arc<Mutex<Vec<MockPty>>>,
+}
+
+impl MockPtySystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns handles to all of the ptys opened so far, in the order
+    /// in which they were opened
+    pub fn ptys(&self) -> Vec<MockPty> {
+        self.ptys.lock().unwrap().clone()
+    }
+
+    /// Returns a handle to the most recently opened pty
+    pub fn last_pty(&self) -> Option<MockPty> {
+        self.ptys.lock().unwrap().last().cloned()
+    }
+}
+
+impl std::fmt::Debug for MockPtySystem {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("MockPtySystem")
+            .field("ptys", &self.ptys.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl PtySystem for MockPtySystem {
+    fn openpty(&self, size: PtySize) -> anyhow::Result<PtyPair> {
+        let pty = MockPty::new(size);
+        self.ptys.lock().unwrap().push(pty.clone());
+        Ok(PtyPair {
+            slave: Box::new(MockSlavePty { pty: pty.clone() }),
+            master: Box::new(MockMasterPty { pty }),
+        })
+    }
+}
+
+struct State {
+    size: PtySize,
+    resizes: Vec<PtySize>,
+    output: VecDeque<u8>,
+    output_closed: bool,
+    input: Vec<u8>,
+    took_writer: bool,
+    commands: Vec<CommandBuilder>,
+    kills: usize,
+    #[cfg(unix)]
+    signals: Vec<crate::unix::Signal>,
+    exit_status: Option<ExitStatus>,
+    process_id: Option<u32>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// The test's handle on a pty opened by `MockPtySystem`.
+/// Every child spawned into the pty shares the same fate: they are
+/// all running until `exit` is called, or until one of them is killed.
+#[derive(Clone)]
+pub struct MockPty {
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for MockPty {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let state = self.lock();
+        fmt.debug_struct("MockPty")
+            .field("size", &state.size)
+            .field("commands", &state.commands)
+            .field("exit_status", &state.exit_status)
+            .finish()
+    }
+}
+
+impl MockPty {
+    fn new(size: PtySize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    size,
+                    resizes: vec![],
+                    output: VecDeque::new(),
+                    output_closed: false,
+                    input: vec![],
+                    took_writer: false,
+                    commands: vec![],
+                    kills: 0,
+                    #[cfg(unix)]
+                    signals: vec![],
+                    exit_status: None,
+                    process_id: None,
+                }),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+
+    /// Modifies the state and wakes up any blocked readers or waiters
+    fn update<T>(&self, func: impl FnOnce(&mut State) -> T) -> T {
+        let result = func(&mut self.lock());
+        self.shared.changed.notify_all();
+        result
+    }
+
+    /// Makes `data` available to readers of the master, as though
+    /// it had been output by the child.
+    /// Data written after `close_output` is discarded.
+    pub fn write_output(&self, data: &[u8]) {
+        self.update(|state| {
+            if !state.output_closed {
+                state.output.extend(data);
+            }
+        });
+    }
+
+    /// Causes readers of the master to see EOF once they have consumed
+    /// any pending output, as happens when every process attached to
+    /// a real pty has gone away
+    pub fn close_output(&self) {
+        self.update(|state| state.output_closed = true);
+    }
+
+    /// Returns everything written to the master so far
+    pub fn input(&self) -> Vec<u8> {
+        self.lock().input.clone()
+    }
+
+    /// Returns everything written to the master since the last call
+    /// to `take_input`, and clears it
+    pub fn take_input(&self) -> Vec<u8> {
+        std::mem::take(&mut self.lock().input)
+    }
+
+    /// Returns the current size of the pty
+    pub fn size(&self) -> PtySize {
+        self.lock().size
+    }
+
+    /// Returns the sizes passed to `MasterPty::resize`, in order
+    pub fn resizes(&self) -> Vec<PtySize> {
+        self.lock().resizes.clone()
+    }
+
+    /// Returns the commands spawned into the pty, in order
+    pub fn commands(&self) -> Vec<CommandBuilder> {
+        self.lock().commands.clone()
+    }
+
+    /// Returns the number of times that a child spawned into the pty,
+    /// or one of its killers, was killed
+    pub fn kill_count(&self) -> usize {
+        self.lock().kills
+    }
+
+    /// Returns the signals sent to the child with `ChildKiller::signal`,
+    /// in order.  Signals don't affect the child; use `exit` to model
+    /// the effect of a signal.
+    #[cfg(unix)]
+    pub fn signals(&self) -> Vec<crate::unix::Signal> {
+        self.lock().signals.clone()
+    }
+
+    /// Returns the exit status of the child, or None if it is still
+    /// running
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.lock().exit_status.clone()
+    }
+
+    /// Causes the child to exit with the given status, waking anything
+    /// blocked in `Child::wait`.  Has no effect if it already exited.
+    pub fn exit(&self, status: ExitStatus) {
+        self.update(|state| {
+            if state.exit_status.is_none() {
+                state.exit_status = Some(status);
+            }
+        });
+    }
+
+    /// Sets the value returned by `Child::process_id`; the default is None
+    pub fn set_process_id(&self, pid: Option<u32>) {
+        self.lock().process_id = pid;
+    }
+
+    fn kill(&self) {
+        self.update(|state| {
+            state.kills += 1;
+            if state.exit_status.is_none() {
+                state.exit_status = Some(ExitStatus::with_signal("Killed"));
+            }
+            // Like a real pty, the output hangs up once the child is gone
+            state.output_closed = true;
+        });
+    }
+}
+
+struct MockMasterPty {
+    pty: MockPty,
+}
+
+impl MasterPty for MockMasterPty {
+    fn resize(&self, size: PtySize) -> Result<(), Error> {
+        self.pty.update(|state| {
+            state.size = size;
+            state.resizes.push(size);
+        });
+        Ok(())
+    }
+
+    fn get_size(&self) -> Result<PtySize, Error> {
+        Ok(self.pty.size())
+    }
+
+    fn try_clone_reader(&self) -> Result<Box<dyn Read + Send>, Error> {
+        Ok(Box::new(MockReader {
+            pty: self.pty.clone(),
+        }))
+    }
+
+    fn take_writer(&self) -> Result<Box<dyn Write + Send>, Error> {
+        let mut state = self.pty.lock();
+        if state.took_writer {
+            bail!("cannot take writer more than once");
+        }
+        state.took_writer = true;
+        Ok(Box::new(MockWriter {
+            pty: self.pty.clone(),
+        }))
+    }
+
+    #[cfg(unix)]
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn as_raw_fd(&self) -> Option<crate::unix::RawFd> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn tty_name(&self) -> Option<std::path::PathBuf> {
+        None
+    }
+}
+
+struct MockReader {
+    pty: MockPty,
+}
+
+impl Read for MockReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut state = self.pty.lock();
+        while state.output.is_empty() && !state.output_closed {
+            state = self.pty.shared.changed.wait(state).unwrap();
+        }
+        let len = buf.len().min(state.output.len());
+        for (dest, src) in buf.iter_mut().zip(state.output.drain(..len)) {
+            *dest = src;
+        }
+        Ok(len)
+    }
+}
+
+struct MockWriter {
+    pty: MockPty,
+}
+
+impl Write for MockWriter {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.pty.lock().input.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+struct MockSlavePty {
+    pty: MockPty,
+}
+
+impl SlavePty for MockSlavePty {
+    /// Records the command; the returned child runs until the test
+    /// calls `MockPty::exit` or the child is killed
+    fn spawn_command(&self, cmd: CommandBuilder) -> Result<Box<dyn Child + Send + Sync>, Error> {
+        self.pty.lock().commands.push(cmd);
+        Ok(Box::new(MockChild {
+            pty: self.pty.clone(),
+        }))
+    }
+}
+
+/// A child spawned into a `MockPty`
+#[derive(Debug)]
+struct MockChild {
+    pty: MockPty,
+}
+
+impl Child for MockChild {
+    fn try_wait(&mut self) -> IoResult<Option<ExitStatus>> {
+        Ok(self.pty.exit_status())
+    }
+
+    fn wait(&mut self) -> IoResult<ExitStatus> {
+        let status = wait_for_status(self.pty.lock(), &self.pty.shared.changed, None, |state| {
+            state.exit_status.as_ref()
+        });
+        Ok(status.expect("waiting without a deadline returns a status"))
+    }
+
+    fn wait_deadline(&mut self, deadline: Instant) -> IoResult<Option<ExitStatus>> {
+        Ok(wait_for_status(
+            self.pty.lock(),
+            &self.pty.shared.changed,
+            Some(deadline),
+            |state| state.exit_status.as_ref(),
+        ))
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        self.pty.lock().process_id
+    }
+
+    #[cfg(windows)]
+    fn as_raw_handle(&self) -> Option<std::os::windows::io::RawHandle> {
+        None
+    }
+}
+
+impl ChildKiller for MockChild {
+    /// Records the kill; a child that is still running exits as
+    /// though it was killed by a signal
+    fn kill(&mut self) -> IoResult<()> {
+        self.pty.kill();
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn signal(&mut self, signal: crate::unix::Signal) -> IoResult<()> {
+        self.pty.lock().signals.push(signal);
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(MockChild {
+            pty: self.pty.clone(),
+        })
+    }
+}
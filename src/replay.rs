@@ -0,0 +1,541 @@
+//! A `PtySystem` that plays back recorded sessions.
+//!
+//! `ReplayPtySystem` makes it possible to test consumers of `MasterPty`
+//! and `Child` deterministically, without spawning real programs.
+//! Its `openpty` returns a pty whose slave "spawns" a child that
+//! produces the output of a `Recording`, and which then exits with
+//! the recorded exit status.
+//!
+//! Recordings can be loaded from the timing and typescript files
+//! produced by `script --timing`, or, with the `asciicast` feature,
+//! from asciicast v2 files such as those produced by
+//! `asciicast::RecordingMasterPty`.
+//!
+//! ```no_run
+//! use portable_pty::replay::{Pacing, Recording, ReplayPtySystem};
+//! use portable_pty::{CommandBuilder, PtySystem};
+//! use std::io::{BufReader, Read};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let timing = BufReader::new(std::fs::File::open("session.timing")?);
+//! let typescript = std::fs::File::open("session.typescript")?;
+//! let recording = Recording::from_script(timing, typescript)?;
+//! let size = recording.size();
+//! let pty_system = ReplayPtySystem::new(recording, Pacing::Immediate);
+//! let pair = pty_system.openpty(size)?;
+//! let mut child = pair.slave.spawn_command(CommandBuilder::new_default_prog())?;
+//! let mut output = String::new();
+//! pair.master.try_clone_reader()?.read_to_string(&mut output)?;
+//! let status = child.wait()?;
+//! # Ok(())
+//! # }
+//! ```
+#[cfg(feature = "asciicast")]
+use crate::asciicast::read_asciicast;
+use crate::{
+    Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtyPair, PtySize, PtySystem,
+    SlavePty, wait_for_status,
+};
+use anyhow::{Context as _, Error, bail};
+use filedescriptor::{AsRawSocketDescriptor, FileDescriptor, POLLOUT, poll, pollfd};
+use std::io::{BufRead, Read, Result as IoResult, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How quickly a recording is played back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pacing {
+    /// Reproduce the timing of the recording
+    RealTime,
+    /// Play back faster by the given factor; 2.0 is twice as fast
+    Accelerated(f64),
+    /// Produce all of the output without any delays
+    Immediate,
+}
+
+impl Pacing {
+    /// Returns when an event recorded at `time` should be played,
+    /// or None if it should be played straight away
+    fn scale(&self, time: Duration) -> Option<Duration> {
+        match *self {
+            Self::RealTime => Some(time),
+            Self::Accelerated(factor) if factor > 0.0 && factor.is_finite() => {
+                Some(time.div_f64(factor))
+            }
+            Self::Accelerated(_) | Self::Immediate => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ReplayEvent {
+    Output(Vec<u8>),
+    /// Only asciicast recordings include resizes
+    #[cfg_attr(not(feature = "asciicast"), allow(dead_code))]
+    Resize(PtySize),
+}
+
+/// A recorded session that can be played back by `ReplayPtySystem`
+#[derive(Debug, Clone)]
+pub struct Recording {
+    size: PtySize,
+    events: Vec<(Duration, ReplayEvent)>,
+    exit_status: ExitStatus,
+}
+
+impl Recording {
+    /// Load a recording from an asciicast v2 file.
+    /// "o" events are played back as output and "r" events change the
+    /// size reported by the master.  The exit status is taken from an
+    /// "x" event, as written by newer versions of asciinema, if
+    /// present; otherwise the child exits successfully.
+    /// This requires the `asciicast` feature.
+    #[cfg(feature = "asciicast")]
+    pub fn from_asciicast(reader: impl BufRead) -> Result<Self, Error> {
+        let (header, events) = read_asciicast(reader)?;
+        let mut recording = Self {
+            size: PtySize {
+                rows: header.height,
+                cols: header.width,
+                ..PtySize::default()
+            },
+            events: vec![],
+            exit_status: ExitStatus::with_exit_code(0),
+        };
+        for event in events {
+            match event.code.as_str() {
+                "o" => recording
+                    .events
+                    .push((event.time, ReplayEvent::Output(event.data.into_bytes()))),
+                "r" => {
+                    let size = event
+                        .data
+                        .split_once('x')
+                        .and_then(|(cols, rows)| Some((cols.parse().ok()?, rows.parse().ok()?)));
+                    match size {
+                        Some((cols, rows)) => recording.events.push((
+                            event.time,
+                            ReplayEvent::Resize(PtySize {
+                                rows,
+                                cols,
+                                ..PtySize::default()
+                            }),
+                        )),
+                        None => bail!("invalid resize event {:?}", event.data),
+                    }
+                }
+                "x" => {
+                    let code: u32 = event
+                        .data
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("invalid exit event {:?}", event.data))?;
+                    recording.exit_status = ExitStatus::with_exit_code(code);
+                }
+                // Input, markers and so on don't affect playback
+                _ => {}
+            }
+        }
+        Ok(recording)
+    }
+
+    /// Load a recording from the timing log and typescript written by
+    /// `script --timing`.  Both the classic timing format of
+    /// `delay length` lines and the advanced format written by
+    /// `script --logging-format advanced` are understood; only output
+    /// is played back.
+    /// The typescript doesn't record the size of the terminal or the
+    /// exit status, so the recording uses the default `PtySize` and
+    /// exits successfully unless changed with `set_size` and
+    /// `set_exit_status`.
+    pub fn from_script(timing: impl BufRead, mut typescript: impl Read) -> Result<Self, Error> {
+        let mut data = vec![];
+        typescript.read_to_end(&mut data)?;
+        // Skip the "Script started on ..." line written by script
+        let mut data = data.as_slice();
+        if data.starts_with(b"Script started")
+            && let Some(newline) = data.iter().position(|&b| b == b'\n')
+        {
+            data = &data[newline + 1..];
+        }
+
+        let mut events = vec![];
+        let mut time = Duration::ZERO;
+        for (idx, line) in timing.lines().enumerate() {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (is_output, delay, length) = match fields.as_slice() {
+                [] => continue,
+                [delay, length] => (true, *delay, Some(*length)),
+                [kind, delay, rest @ ..] => (*kind == "O", *delay, rest.first().copied()),
+                _ => bail!("line {}: malformed timing entry {:?}", idx + 1, line),
+            };
+            let delay: f64 = delay
+                .parse()
+                .with_context(|| format!("line {}: invalid delay {:?}", idx + 1, delay))?;
+            if !(delay.is_finite() && delay >= 0.0) {
+                bail!("line {}: invalid delay {}", idx + 1, delay);
+            }
+            time += Duration::from_secs_f64(delay);
+            if !is_output {
+                continue;
+            }
+            let length: usize = length
+                .unwrap_or_default()
+                .parse()
+                .with_context(|| format!("line {}: invalid length in {:?}", idx + 1, line))?;
+            if length > data.len() {
+                bail!("line {}: timing log extends beyond the typescript", idx + 1);
+            }
+            let (chunk, rest) = data.split_at(length);
+            events.push((time, ReplayEvent::Output(chunk.to_vec())));
+            data = rest;
+        }
+
+        Ok(Self {
+            size: PtySize::default(),
+            events,
+            exit_status: ExitStatus::with_exit_code(0),
+        })
+    }
+
+    /// Returns the size of the terminal at the start of the recording
+    pub fn size(&self) -> PtySize {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: PtySize) {
+        self.size = size;
+    }
+
+    /// Returns the status that the child exits with once playback
+    /// has completed
+    pub fn exit_status(&self) -> &ExitStatus {
+        &self.exit_status
+    }
+
+    pub fn set_exit_status(&mut self, status: ExitStatus) {
+        self.exit_status = status;
+    }
+
+    /// Returns the time of the last event in the recording
+    pub fn duration(&self) -> Duration {
+        self.events
+            .last()
+            .map(|(time, _)| *time)
+            .unwrap_or_default()
+    }
+}
+
+/// A `PtySystem` whose ptys play back a `Recording`.
+/// See the module documentation for an example.
+pub struct ReplayPtySystem {
+    recording: Arc<Recording>,
+    pacing: Pacing,
+}
+
+impl ReplayPtySystem {
+    pub fn new(recording: Recording, pacing: Pacing) -> Self {
+        Self {
+            recording: Arc::new(recording),
+            pacing,
+        }
+    }
+}
+
+impl PtySystem for ReplayPtySystem {
+    fn openpty(&self, size: PtySize) -> anyhow::Result<PtyPair> {
+        // A socket pair rather than a pipe, as only sockets can be
+        // made non-blocking and polled on Windows
+        let (read, mut write) = filedescriptor::socketpair()?;
+        write.set_non_blocking(true)?;
+        let size = Arc::new(Mutex::new(size));
+        Ok(PtyPair {
+            slave: Box::new(ReplaySlavePty {
+                recording: Arc::clone(&self.recording),
+                pacing: self.pacing,
+                size: Arc::clone(&size),
+                output: Mutex::new(Some(write)),
+            }),
+            master: Box::new(ReplayMasterPty {
+                size,
+                output: read,
+                took_writer: AtomicBool::new(false),
+            }),
+        })
+    }
+}
+
+/// The master end of a replayed pty.  Output of the recording is read
+/// from it; input written to it is discarded.
+struct ReplayMasterPty {
+    size: Arc<Mutex<PtySize>>,
+    output: FileDescriptor,
+    took_writer: AtomicBool,
+}
+
+impl MasterPty for ReplayMasterPty {
+    fn resize(&self, size: PtySize) -> Result<(), Error> {
+        *self.size.lock().unwrap() = size;
+        Ok(())
+    }
+
+    fn get_size(&self) -> Result<PtySize, Error> {
+        Ok(*self.size.lock().unwrap())
+    }
+
+    fn try_clone_reader(&self) -> Result<Box<dyn Read + Send>, Error> {
+        Ok(Box::new(self.output.try_clone()?))
+    }
+
+    fn take_writer(&self) -> Result<Box<dyn Write + Send>, Error> {
+        if self.took_writer.swap(true, Ordering::SeqCst) {
+            bail!("cannot take writer more than once");
+        }
+        Ok(Box::new(std::io::sink()))
+    }
+
+    #[cfg(unix)]
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn as_raw_fd(&self) -> Option<crate::unix::RawFd> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn tty_name(&self) -> Option<std::path::PathBuf> {
+        None
+    }
+}
+
+struct ReplaySlavePty {
+    recording: Arc<Recording>,
+    pacing: Pacing,
+    size: Arc<Mutex<PtySize>>,
+    /// The write end of the output socket, until playback starts
+    output: Mutex<Option<FileDescriptor>>,
+}
+
+impl SlavePty for ReplaySlavePty {
+    /// Starts playing back the recording.  The command itself is
+    /// ignored, and only one child can be spawned into each pty.
+    fn spawn_command(&self, _cmd: CommandBuilder) -> Result<Box<dyn Child + Send + Sync>, Error> {
+        let Some(output) = self.output.lock().unwrap().take() else {
+            bail!("a replayed pty can only spawn a single child");
+        };
+        let playback = Arc::new(Playback::default());
+        let player = Player {
+            recording: Arc::clone(&self.recording),
+            pacing: self.pacing,
+            size: Arc::clone(&self.size),
+            output,
+            playback: Arc::clone(&playback),
+        };
+        std::thread::Builder::new()
+            .name("portable-pty-replay".to_string())
+            .spawn(move || player.run())?;
+        Ok(Box::new(ReplayChild { playback }))
+    }
+}
+
+#[derive(Default)]
+struct PlaybackState {
+    killed: bool,
+    status: Option<ExitStatus>,
+}
+
+/// State shared between the player thread and the child and its killers
+#[derive(Default)]
+struct Playback {
+    state: Mutex<PlaybackState>,
+    changed: Condvar,
+}
+
+impl Playback {
+    fn kill(&self) {
+        self.state.lock().unwrap().killed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// The most output that the player writes at once
+const WRITE_CHUNK: usize = 4096;
+
+/// How long the player waits for the output to drain before checking
+/// whether it has been killed
+const WRITE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+struct Player {
+    recording: Arc<Recording>,
+    pacing: Pacing,
+    size: Arc<Mutex<PtySize>>,
+    output: FileDescriptor,
+    playback: Arc<Playback>,
+}
+
+impl Player {
+    /// Writes `data` to the output, a chunk at a time.  The output is
+    /// non-blocking so that playback can still be killed once nobody
+    /// reads it and it has filled up.
+    /// Returns false if playback should stop.
+    fn write_output(&mut self, mut data: &[u8]) -> bool {
+        while !data.is_empty() {
+            if self.playback.state.lock().unwrap().killed {
+                return false;
+            }
+            match self.output.write(&data[..data.len().min(WRITE_CHUNK)]) {
+                Ok(0) => return false,
+                Ok(n) => data = &data[n..],
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    let mut pfd = [pollfd {
+                        fd: self.output.as_socket_descriptor(),
+                        events: POLLOUT,
+                        revents: 0,
+                    }];
+                    let _ = poll(&mut pfd, Some(WRITE_POLL_INTERVAL));
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                // Nobody is reading the output any more
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    fn run(mut self) {
+        let start = Instant::now();
+        let recording = Arc::clone(&self.recording);
+        for (time, event) in &recording.events {
+            // Wait until the event is due, or we are killed
+            let mut state = self.playback.state.lock().unwrap();
+            if let Some(due) = self.pacing.scale(*time) {
+                let deadline = start + due;
+                while !state.killed {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    state = self
+                        .playback
+                        .changed
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0;
+                }
+            }
+            if state.killed {
+                break;
+            }
+            drop(state);
+
+            match event {
+                ReplayEvent::Output(data) => {
+                    if !self.write_output(data) {
+                        break;
+                    }
+                }
+                ReplayEvent::Resize(size) => {
+                    let mut current = self.size.lock().unwrap();
+                    current.rows = size.rows;
+                    current.cols = size.cols;
+                }
+            }
+        }
+
+        // Closing the output lets readers see EOF
+        drop(self.output);
+
+        let mut state = self.playback.state.lock().unwrap();
+        state.status = Some(if state.killed {
+            ExitStatus::with_signal("Killed")
+        } else {
+            self.recording.exit_status.clone()
+        });
+        self.playback.changed.notify_all();
+    }
+}
+
+/// The "process" playing back a recording
+struct ReplayChild {
+    playback: Arc<Playback>,
+}
+
+impl std::fmt::Debug for ReplayChild {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("ReplayChild").finish()
+    }
+}
+
+impl Child for ReplayChild {
+    fn try_wait(&mut self) -> IoResult<Option<ExitStatus>> {
+        Ok(self.playback.state.lock().unwrap().status.clone())
+    }
+
+    fn wait(&mut self) -> IoResult<ExitStatus> {
+        let state = self.playback.state.lock().unwrap();
+        let status = wait_for_status(state, &self.playback.changed, None, |state| {
+            state.status.as_ref()
+        });
+        Ok(status.expect("waiting without a deadline returns a status"))
+    }
+
+    fn wait_deadline(&mut self, deadline: Instant) -> IoResult<Option<ExitStatus>> {
+        let state = self.playback.state.lock().unwrap();
+        Ok(wait_for_status(
+            state,
+            &self.playback.changed,
+            Some(deadline),
+            |state| state.status.as_ref(),
+        ))
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        None
+    }
+
+    #[cfg(windows)]
+    fn as_raw_handle(&self) -> Option<std::os::windows::io::RawHandle> {
+        None
+    }
+}
+
+impl ChildKiller for ReplayChild {
+    /// Stops playback; the child then exits as though it was killed
+    fn kill(&mut self) -> IoResult<()> {
+        self.playback.kill();
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(ReplayChildKiller {
+            playback: Arc::clone(&self.playback),
+        })
+    }
+}
+
+struct ReplayChildKiller {
+    playback: Arc<Playback>,
+}
+
+impl std::fmt::Debug for ReplayChildKiller {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("ReplayChildKiller").finish()
+    }
+}
+
+impl ChildKiller for ReplayChildKiller {
+    fn kill(&mut self) -> IoResult<()> {
+        self.playback.kill();
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(Self {
+            playback: Arc::clone(&self.playback),
+        })
+    }
+}
@@ -0,0 +1,190 @@
+//! Run a command in a pty to completion and capture its output.
+//!
+//! Collecting all of the output of a command without losing the tail
+//! end of it, and without deadlocking, requires some care: the output
+//! has to be drained by a separate thread while waiting for the child,
+//! input has to be fed without blocking the waiter, and the master has
+//! to be released once the child has exited so that the reader sees EOF
+//! on every platform.  `run` and `run_with` encode that ordering.
+//!
+//! ```no_run
+//! use portable_pty::CommandBuilder;
+//! use portable_pty::run::{RunOptions, run};
+//! use std::time::Duration;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut options = RunOptions::new();
+//! options.set_timeout(Some(Duration::from_secs(10)));
+//! let result = run(CommandBuilder::new("ls"), &options)?;
+//! println!("{}", String::from_utf8_lossy(&result.output));
+//! assert!(result.status.success());
+//! # Ok(())
+//! # }
+//! ```
+use crate::session::{drain_output, spawn_reader};
+use crate::{Child, CommandBuilder, ExitStatus, PtyPair, PtySize, PtySystem, native_pty_system};
+use anyhow::{Error, bail};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// How long to keep draining output once the child has exited, when
+/// that leaves less time than this before the timeout
+const DRAIN_GRACE: Duration = Duration::from_secs(1);
+
+/// Options for `run` and `run_with`
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    size: PtySize,
+    stdin: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+}
+
+impl RunOptions {
+    /// Returns the default options: a default sized pty, no input
+    /// and no timeout
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the size of the pty that the command runs in
+    pub fn set_size(&mut self, size: PtySize) {
+        self.size = size;
+    }
+
+    pub fn size(&self) -> PtySize {
+        self.size
+    }
+
+    /// Sets data to be written to the pty once the command has been
+    /// spawned.  The writer is closed afterwards, which on unix sends
+    /// the terminal's EOF character so that commands that read until
+    /// the end of their input terminate.
+    /// Bear in mind that the terminal echoes input by default, so it
+    /// is likely to appear in the captured output too.
+    pub fn set_stdin(&mut self, stdin: Option<Vec<u8>>) {
+        self.stdin = stdin;
+    }
+
+    pub fn stdin(&self) -> Option<&[u8]> {
+        self.stdin.as_deref()
+    }
+
+    /// Limits how long the command may run for.  If it is still running
+    /// once the timeout elapses it is killed and the result has
+    /// `timed_out` set.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+/// The outcome of `run` and `run_with`
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    /// Everything that the command wrote to the pty
+    pub output: Vec<u8>,
+    /// How the command exited
+    pub status: ExitStatus,
+    /// True if the command was killed because it exceeded the timeout,
+    /// or if the pty was still held open by other processes when the
+    /// timeout, or the grace period after the exit, elapsed, in which
+    /// case `output` may be incomplete
+    pub timed_out: bool,
+}
+
+/// Runs `cmd` in a new pty from the native pty system.
+/// See `run_with` for the details.
+pub fn run(cmd: CommandBuilder, options: &RunOptions) -> Result<RunResult, Error> {
+    run_with(&*native_pty_system(), cmd, options)
+}
+
+/// Runs `cmd` in a new pty opened from `pty_system`, feeding it the
+/// configured input and capturing its output until it exits.
+///
+/// Output is collected until the pty reports EOF.  If the command
+/// leaves behind background processes that hold the pty open, output
+/// is collected for as long as the timeout allows, or for a short grace
+/// period after the command exited if that is later, and whatever was
+/// captured by then is returned; without a timeout this waits for
+/// those processes to go away too.
+pub fn run_with(
+    pty_system: &dyn PtySystem,
+    cmd: CommandBuilder,
+    options: &RunOptions,
+) -> Result<RunResult, Error> {
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+
+    let PtyPair { master, slave } = pty_system.openpty(options.size)?;
+    let mut child = slave.spawn_command(cmd)?;
+    // Our copy of the slave would otherwise keep the pty open after
+    // the child has exited, and the reader would never see EOF
+    drop(slave);
+
+    // Drain the output on a separate thread, so that the child can't
+    // block on a full pty buffer while we are waiting for it
+    let (_reader, output) = spawn_reader(&*master, "portable-pty-run-reader")?;
+
+    // Feed the input on yet another thread; the child might not read
+    // it all before it exits, which must not stall the wait below
+    if let Some(stdin) = options.stdin.clone() {
+        let mut writer = master.take_writer()?;
+        std::thread::Builder::new()
+            .name("portable-pty-run-writer".to_string())
+            .spawn(move || {
+                let _ = writer.write_all(&stdin);
+                let _ = writer.flush();
+            })?;
+    }
+
+    let mut timed_out = false;
+    let status = match (deadline, options.timeout) {
+        (Some(deadline), Some(timeout)) => match child.wait_deadline(deadline) {
+            Ok(Some(status)) => status,
+            Ok(None) => {
+                timed_out = true;
+                kill_and_reap(&mut *child, timeout)?
+            }
+            Err(err) => {
+                // Don't leave the child running behind our back
+                let _ = kill_and_reap(&mut *child, timeout);
+                return Err(err.into());
+            }
+        },
+        _ => child.wait()?,
+    };
+
+    // Now that the child is gone, release the master.  On Windows the
+    // reader only sees EOF once the pseudoconsole has been closed.
+    drop(master);
+
+    // The output can lag behind the exit of the child, so allow for
+    // that even if the deadline has passed, or is about to
+    let drain_deadline = deadline.map(|deadline| deadline.max(Instant::now() + DRAIN_GRACE));
+    // If something still holds the pty open, keep what we have and
+    // leave the reader thread behind
+    let (output, drained) = drain_output(&output, drain_deadline);
+
+    Ok(RunResult {
+        output,
+        status,
+        timed_out: timed_out || !drained,
+    })
+}
+
+/// Kills `child` and waits for it, giving up if it hasn't exited
+/// `timeout` after being killed
+fn kill_and_reap(child: &mut dyn Child, timeout: Duration) -> Result<ExitStatus, Error> {
+    let killed = child.kill();
+    // Don't trust the kill to work any more than the child to finish
+    // in time
+    match child.wait_deadline(Instant::now() + timeout)? {
+        Some(status) => Ok(status),
+        None => {
+            killed?;
+            bail!("child did not exit within {timeout:?} of being killed")
+        }
+    }
+}
@@ -0,0 +1,616 @@
+//! A headless terminal screen model for captured pty output.
+//!
+//! `Screen` interprets the output of a program running in a pty in
+//! the same way that a terminal emulator would, maintaining a grid of
+//! cells, the cursor position and the attributes of each cell.  This
+//! makes it possible to assert on what the user would actually see,
+//! rather than on the raw stream of bytes, which for full screen
+//! programs is dominated by cursor movement, clears and redraws.
+//!
+//! Only the commonly used subset of the VT100/xterm control sequences
+//! is implemented: cursor movement, erasing, inserting and deleting
+//! lines and characters, scroll regions, SGR attributes including
+//! 256-color and truecolor, the alternate screen and the window title.
+//! Every character is treated as occupying a single cell.
+//!
+//! ```
+//! use portable_pty::screen::Screen;
+//! use portable_pty::PtySize;
+//!
+//! let mut screen = Screen::new(PtySize::default());
+//! screen.process(b"hello\r\n\x1b[1mworld\x1b[2;3H");
+//! assert_eq!(screen.row_text(1), "world");
+//! assert!(screen.cell(1, 0).unwrap().attributes.bold);
+//! assert_eq!((screen.cursor().row, screen.cursor().col), (1, 2));
+//! ```
+use crate::PtySize;
+use vte::{Params, Parser, Perform};
+
+/// The color of the foreground or background of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    /// The terminal's default color
+    #[default]
+    Default,
+    /// One of the 256 palette colors; 0-15 are the ANSI colors
+    Indexed(u8),
+    /// A truecolor value
+    Rgb(u8, u8, u8),
+}
+
+/// The rendition of a cell, as set by SGR sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Attributes {
+    pub foreground: Color,
+    pub background: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+/// A single character cell of the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub attributes: Attributes,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            attributes: Attributes::default(),
+        }
+    }
+}
+
+/// A zero based position on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CursorPosition {
+    pub row: usize,
+    pub col: usize,
+}
+
+/// A virtual terminal screen.  Feed it the output of a pty with
+/// `process`, or by using it as a `std::io::Write`.
+pub struct Screen {
+    parser: Parser,
+    state: State,
+}
+
+impl Screen {
+    /// Create a blank screen with the dimensions from `size`
+    pub fn new(size: PtySize) -> Self {
+        Self {
+            parser: Parser::new(),
+            state: State::new(size),
+        }
+    }
+
+    /// Interpret `bytes` of output from the pty
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.parser.advance(&mut self.state, bytes);
+    }
+
+    /// Change the dimensions of the screen.  This should be called
+    /// alongside `MasterPty::resize` so that the screen matches what
+    /// the program running in the pty believes its size to be.
+    /// Content that no longer fits is truncated, and new cells are
+    /// blank.
+    pub fn resize(&mut self, size: PtySize) {
+        self.state.resize(size);
+    }
+
+    pub fn size(&self) -> PtySize {
+        self.state.size
+    }
+
+    /// Returns the position of the cursor
+    pub fn cursor(&self) -> CursorPosition {
+        self.state.cursor
+    }
+
+    /// Returns true unless the cursor was hidden with `CSI ? 25 l`
+    pub fn cursor_visible(&self) -> bool {
+        self.state.cursor_visible
+    }
+
+    /// Returns true if the alternate screen is active
+    pub fn alternate_screen(&self) -> bool {
+        self.state.saved_primary.is_some()
+    }
+
+    /// Returns the window title, as set by OSC 0 or OSC 2
+    pub fn title(&self) -> &str {
+        &self.state.title
+    }
+
+    /// Returns the cell at the zero based `row` and `col`
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.state.grid.get(row)?.get(col)
+    }
+
+    /// Returns the text of the zero based `row`, with trailing blanks
+    /// removed
+    pub fn row_text(&self, row: usize) -> String {
+        match self.state.grid.get(row) {
+            Some(cells) => cells
+                .iter()
+                .map(|cell| cell.ch)
+                .collect::<String>()
+                .trim_end()
+                .to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// Returns the text of the visible screen, one line per row, with
+    /// trailing blanks and blank rows at the bottom removed
+    pub fn contents(&self) -> String {
+        let rows: Vec<String> = (0..self.state.grid.len())
+            .map(|row| self.row_text(row))
+            .collect();
+        rows.join("\n").trim_end().to_string()
+    }
+}
+
+impl std::io::Write for Screen {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.process(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Screen {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("Screen")
+            .field("size", &self.state.size)
+            .field("cursor", &self.state.cursor)
+            .field("contents", &self.contents())
+            .finish()
+    }
+}
+
+/// The terminal state that is driven by the parser
+struct State {
+    size: PtySize,
+    grid: Vec<Vec<Cell>>,
+    cursor: CursorPosition,
+    /// Set when a character was printed in the last column; the
+    /// cursor wraps to the next line before the next character
+    wrap_pending: bool,
+    autowrap: bool,
+    cursor_visible: bool,
+    pen: Attributes,
+    saved_cursor: Option<(CursorPosition, Attributes)>,
+    /// The inclusive range of rows that scroll
+    scroll_top: usize,
+    scroll_bottom: usize,
+    /// The primary screen contents while the alternate screen is active
+    saved_primary: Option<Vec<Vec<Cell>>>,
+    title: String,
+}
+
+impl State {
+    fn new(size: PtySize) -> Self {
+        let rows = size.rows.max(1) as usize;
+        let cols = size.cols.max(1) as usize;
+        Self {
+            size,
+            grid: vec![vec![Cell::default(); cols]; rows],
+            cursor: CursorPosition::default(),
+            wrap_pending: false,
+            autowrap: true,
+            cursor_visible: true,
+            pen: Attributes::default(),
+            saved_cursor: None,
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            saved_primary: None,
+            title: String::new(),
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.grid.len()
+    }
+
+    fn cols(&self) -> usize {
+        self.grid[0].len()
+    }
+
+    fn resize(&mut self, size: PtySize) {
+        let rows = size.rows.max(1) as usize;
+        let cols = size.cols.max(1) as usize;
+        fn resize_grid(grid: &mut Vec<Vec<Cell>>, rows: usize, cols: usize) {
+            grid.resize(rows, vec![Cell::default(); cols]);
+            for row in grid.iter_mut() {
+                row.resize(cols, Cell::default());
+            }
+        }
+        resize_grid(&mut self.grid, rows, cols);
+        if let Some(primary) = self.saved_primary.as_mut() {
+            resize_grid(primary, rows, cols);
+        }
+        self.size = size;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.cursor.row = self.cursor.row.min(rows - 1);
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.wrap_pending = false;
+    }
+
+    /// A blank cell, which takes the current background color
+    fn blank(&self) -> Cell {
+        Cell {
+            ch: ' ',
+            attributes: Attributes {
+                background: self.pen.background,
+                ..Attributes::default()
+            },
+        }
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.rows() - 1);
+        self.cursor.col = col.min(self.cols() - 1);
+        self.wrap_pending = false;
+    }
+
+    /// Scroll the scroll region up by `count` lines
+    fn scroll_up(&mut self, count: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let count = count.min(bottom - top + 1);
+        let blank = vec![self.blank(); self.cols()];
+        self.grid[top..=bottom].rotate_left(count);
+        for row in &mut self.grid[bottom + 1 - count..=bottom] {
+            row.clone_from(&blank);
+        }
+    }
+
+    /// Scroll the scroll region down by `count` lines
+    fn scroll_down(&mut self, count: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let count = count.min(bottom - top + 1);
+        let blank = vec![self.blank(); self.cols()];
+        self.grid[top..=bottom].rotate_right(count);
+        for row in &mut self.grid[top..top + count] {
+            row.clone_from(&blank);
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows() {
+            self.cursor.row += 1;
+        }
+        self.wrap_pending = false;
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+        self.wrap_pending = false;
+    }
+
+    fn erase_cells(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        let blank = self.blank();
+        let cols = cols.start.min(self.cols())..cols.end.min(self.cols());
+        for cell in &mut self.grid[row][cols] {
+            *cell = blank;
+        }
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let CursorPosition { row, col } = self.cursor;
+        let (rows, cols) = (self.rows(), self.cols());
+        match mode {
+            0 => {
+                self.erase_cells(row, col..cols);
+                for r in row + 1..rows {
+                    self.erase_cells(r, 0..cols);
+                }
+            }
+            1 => {
+                for r in 0..row {
+                    self.erase_cells(r, 0..cols);
+                }
+                self.erase_cells(row, 0..col + 1);
+            }
+            2 | 3 => {
+                for r in 0..rows {
+                    self.erase_cells(r, 0..cols);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let CursorPosition { row, col } = self.cursor;
+        let cols = self.cols();
+        match mode {
+            0 => self.erase_cells(row, col..cols),
+            1 => self.erase_cells(row, 0..col + 1),
+            2 => self.erase_cells(row, 0..cols),
+            _ => {}
+        }
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        let row = self.cursor.row;
+        if row < self.scroll_top || row > self.scroll_bottom {
+            return;
+        }
+        let top = self.scroll_top;
+        self.scroll_top = row;
+        self.scroll_down(count);
+        self.scroll_top = top;
+        self.cursor.col = 0;
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        let row = self.cursor.row;
+        if row < self.scroll_top || row > self.scroll_bottom {
+            return;
+        }
+        let top = self.scroll_top;
+        self.scroll_top = row;
+        self.scroll_up(count);
+        self.scroll_top = top;
+        self.cursor.col = 0;
+    }
+
+    fn insert_chars(&mut self, count: usize) {
+        let CursorPosition { row, col } = self.cursor;
+        let blank = self.blank();
+        let line = &mut self.grid[row][col..];
+        let count = count.min(line.len());
+        line.rotate_right(count);
+        for cell in &mut line[..count] {
+            *cell = blank;
+        }
+    }
+
+    fn delete_chars(&mut self, count: usize) {
+        let CursorPosition { row, col } = self.cursor;
+        let blank = self.blank();
+        let line = &mut self.grid[row][col..];
+        let count = count.min(line.len());
+        line.rotate_left(count);
+        let len = line.len();
+        for cell in &mut line[len - count..] {
+            *cell = blank;
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some((self.cursor, self.pen));
+    }
+
+    fn restore_cursor(&mut self) {
+        let (cursor, pen) = self.saved_cursor.unwrap_or_default();
+        self.pen = pen;
+        self.move_to(cursor.row, cursor.col);
+    }
+
+    fn set_alternate_screen(&mut self, enable: bool) {
+        let blank_grid = vec![vec![Cell::default(); self.cols()]; self.rows()];
+        if enable && self.saved_primary.is_none() {
+            self.save_cursor();
+            self.saved_primary = Some(std::mem::replace(&mut self.grid, blank_grid));
+        } else if !enable && let Some(primary) = self.saved_primary.take() {
+            self.grid = primary;
+            self.restore_cursor();
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            7 => self.autowrap = enable,
+            25 => self.cursor_visible = enable,
+            47 | 1047 | 1049 => self.set_alternate_screen(enable),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let mut iter = params.iter();
+        if params.is_empty() {
+            self.pen = Attributes::default();
+            return;
+        }
+        while let Some(param) = iter.next() {
+            match param[0] {
+                0 => self.pen = Attributes::default(),
+                1 => self.pen.bold = true,
+                2 => self.pen.dim = true,
+                3 => self.pen.italic = true,
+                4 => self.pen.underline = param.get(1) != Some(&0),
+                5 | 6 => self.pen.blink = true,
+                7 => self.pen.inverse = true,
+                8 => self.pen.hidden = true,
+                9 => self.pen.strikethrough = true,
+                21 => self.pen.underline = true,
+                22 => {
+                    self.pen.bold = false;
+                    self.pen.dim = false;
+                }
+                23 => self.pen.italic = false,
+                24 => self.pen.underline = false,
+                25 => self.pen.blink = false,
+                27 => self.pen.inverse = false,
+                28 => self.pen.hidden = false,
+                29 => self.pen.strikethrough = false,
+                n @ 30..=37 => self.pen.foreground = Color::Indexed((n - 30) as u8),
+                38 => self.pen.foreground = extended_color(param, &mut iter),
+                39 => self.pen.foreground = Color::Default,
+                n @ 40..=47 => self.pen.background = Color::Indexed((n - 40) as u8),
+                48 => self.pen.background = extended_color(param, &mut iter),
+                49 => self.pen.background = Color::Default,
+                n @ 90..=97 => self.pen.foreground = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => self.pen.background = Color::Indexed((n - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Parses the color following SGR 38 or 48, which is either given as
+/// subparameters (`38:5:n`, `38:2::r:g:b`) or as the following
+/// parameters (`38;5;n`, `38;2;r;g;b`).
+fn extended_color<'a>(param: &[u16], iter: &mut impl Iterator<Item = &'a [u16]>) -> Color {
+    let values: Vec<u16> = if param.len() > 1 {
+        let mut values = param[1..].to_vec();
+        // The colon form may include an empty color space id
+        if values.first() == Some(&2) && values.len() == 5 {
+            values.remove(1);
+        }
+        values
+    } else {
+        match iter.next().map(|p| p[0]) {
+            Some(5) => vec![5, iter.next().map(|p| p[0]).unwrap_or(0)],
+            Some(2) => {
+                let mut values = vec![2];
+                values.extend(iter.take(3).map(|p| p[0]));
+                values
+            }
+            _ => vec![],
+        }
+    };
+    match values.as_slice() {
+        [5, n, ..] => Color::Indexed(*n as u8),
+        [2, r, g, b, ..] => Color::Rgb(*r as u8, *g as u8, *b as u8),
+        _ => Color::Default,
+    }
+}
+
+/// Returns the `idx`th parameter, treating missing and zero values
+/// as `default`, as is conventional for counts and positions.
+fn param(params: &Params, idx: usize, default: u16) -> u16 {
+    match params.iter().nth(idx).map(|p| p[0]) {
+        None | Some(0) => default,
+        Some(n) => n,
+    }
+}
+
+impl Perform for State {
+    fn print(&mut self, c: char) {
+        if self.wrap_pending {
+            self.cursor.col = 0;
+            self.line_feed();
+        }
+        let CursorPosition { row, col } = self.cursor;
+        self.grid[row][col] = Cell {
+            ch: c,
+            attributes: self.pen,
+        };
+        if col + 1 < self.cols() {
+            self.cursor.col += 1;
+        } else if self.autowrap {
+            self.wrap_pending = true;
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' | 0x0b | 0x0c => self.line_feed(),
+            b'\r' => {
+                self.cursor.col = 0;
+                self.wrap_pending = false;
+            }
+            0x08 => {
+                self.cursor.col = self.cursor.col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            b'\t' => {
+                let next = (self.cursor.col / 8 + 1) * 8;
+                self.cursor.col = next.min(self.cols() - 1);
+            }
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        if let [b"0" | b"2", title, ..] = params {
+            self.title = String::from_utf8_lossy(title).to_string();
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
+        if ignore {
+            return;
+        }
+        let CursorPosition { row, col } = self.cursor;
+        let n = param(params, 0, 1) as usize;
+        match (intermediates, c) {
+            ([b'?'], 'h') | ([b'?'], 'l') => {
+                for mode in params.iter() {
+                    self.set_private_mode(mode[0], c == 'h');
+                }
+            }
+            ([], 'A') => self.move_to(row.saturating_sub(n), col),
+            ([], 'B') => self.move_to(row + n, col),
+            ([], 'C') => self.move_to(row, col + n),
+            ([], 'D') => self.move_to(row, col.saturating_sub(n)),
+            ([], 'E') => self.move_to(row + n, 0),
+            ([], 'F') => self.move_to(row.saturating_sub(n), 0),
+            ([], 'G') | ([], '`') => self.move_to(row, n - 1),
+            ([], 'd') => self.move_to(n - 1, col),
+            ([], 'H') | ([], 'f') => {
+                let col = param(params, 1, 1) as usize;
+                self.move_to(n - 1, col - 1);
+            }
+            ([], 'J') => self.erase_in_display(param(params, 0, 0)),
+            ([], 'K') => self.erase_in_line(param(params, 0, 0)),
+            ([], 'L') => self.insert_lines(n),
+            ([], 'M') => self.delete_lines(n),
+            ([], '@') => self.insert_chars(n),
+            ([], 'P') => self.delete_chars(n),
+            ([], 'X') => self.erase_cells(row, col..col + n),
+            ([], 'S') => self.scroll_up(n),
+            ([], 'T') => self.scroll_down(n),
+            ([], 'm') => self.select_graphic_rendition(params),
+            ([], 'r') => {
+                let top = param(params, 0, 1) as usize - 1;
+                let bottom = (param(params, 1, self.rows() as u16) as usize).min(self.rows()) - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            ([], 's') => self.save_cursor(),
+            ([], 'u') => self.restore_cursor(),
+            _ => log::trace!("unhandled CSI {:?} {:?} {}", params, intermediates, c),
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.line_feed(),
+            ([], b'E') => {
+                self.cursor.col = 0;
+                self.line_feed();
+            }
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => *self = State::new(self.size),
+            _ => {}
+        }
+    }
+}
@@ -18,7 +18,7 @@ use std::io::{Read, Result as IoResult, Write};
 #[cfg(unix)]
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 type Handle = Arc<SerialPort>;
 
@@ -156,6 +156,25 @@ impl Child for SerialChild {
         }
     }
 
+    fn wait_deadline(&mut self, deadline: Instant) -> IoResult<Option<ExitStatus>> {
+        // As with `wait`, the "child" only terminates when the carrier
+        // detect signal can no longer be read, so check that at the same
+        // interval until the deadline.
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep((deadline - now).min(Duration::from_secs(5)));
+
+            let port = &self.port;
+            if let Err(err) = port.read_cd() {
+                log::error!("Error reading carrier detect: {:#}", err);
+                return Ok(Some(ExitStatus::with_exit_code(1)));
+            }
+        }
+    }
+
     fn process_id(&self) -> Option<u32> {
         None
     }
@@ -0,0 +1,283 @@
+//! An owned pty session that shuts down in the correct order.
+//!
+//! Using a `PtyPair` directly means remembering to drop the slave once
+//! the child has been spawned, to drain the output on a separate thread,
+//! and to release things in the right order when done so that neither
+//! the child nor the reader is left behind.  `PtySession` owns all of
+//! those parts and takes care of that.
+//!
+//! When a `PtySession` is dropped, or `close` is called, the child is
+//! killed according to its kill policy if it is still running and then
+//! waited for, the writer and master are closed, and the remaining
+//! output is drained.  `detach` hands the parts back without doing any
+//! of that.
+//!
+//! ```no_run
+//! use portable_pty::session::PtySession;
+//! use portable_pty::{CommandBuilder, PtySize, native_pty_system};
+//! use std::io::Write;
+//! use std::time::Duration;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let pair = native_pty_system().openpty(PtySize::default())?;
+//! let mut session = PtySession::spawn(pair, CommandBuilder::new("bash"))?;
+//! session.write_all(b"echo hello\n")?;
+//! let chunk = session.output().recv_timeout(Duration::from_secs(1))?;
+//! let closed = session.close()?;
+//! println!("exited with {}", closed.status);
+//! # Ok(())
+//! # }
+//! ```
+use crate::{Child, CommandBuilder, ExitStatus, MasterPty, PtyPair};
+use anyhow::Error;
+use std::io::{Read, Write};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The default for `PtySession::set_drain_timeout`
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A child running in a pty, together with the master, the writer and
+/// a thread that reads the output of the pty.
+/// See the module documentation for details.
+pub struct PtySession {
+    master: Option<Box<dyn MasterPty + Send>>,
+    writer: Option<Box<dyn Write + Send>>,
+    child: Option<Box<dyn Child + Send + Sync>>,
+    reader: Option<JoinHandle<()>>,
+    output: Receiver<Vec<u8>>,
+    drain_timeout: Duration,
+}
+
+/// The result of `PtySession::close`
+#[derive(Debug, Clone)]
+pub struct ClosedSession {
+    /// How the child exited
+    pub status: ExitStatus,
+    /// Output that had not yet been received from `PtySession::output`
+    pub output: Vec<u8>,
+    /// False if the output was still open after the drain timeout,
+    /// which happens when other processes hold the pty open
+    pub drained: bool,
+}
+
+/// The parts of a `PtySession`, returned by `PtySession::detach`
+pub struct SessionParts {
+    pub master: Box<dyn MasterPty + Send>,
+    pub writer: Box<dyn Write + Send>,
+    pub child: Box<dyn Child + Send + Sync>,
+    /// Receives the output of the pty; it disconnects at EOF
+    pub output: Receiver<Vec<u8>>,
+}
+
+impl std::fmt::Debug for PtySession {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("PtySession")
+            .field("child", &self.child)
+            .field("drain_timeout", &self.drain_timeout)
+            .finish()
+    }
+}
+
+impl PtySession {
+    /// Spawns `cmd` into the slave of `pair`, which is then dropped,
+    /// and starts reading the output of the master
+    pub fn spawn(pair: PtyPair, cmd: CommandBuilder) -> Result<Self, Error> {
+        let PtyPair { master, slave } = pair;
+        let child = slave.spawn_command(cmd)?;
+        // Our copy of the slave would otherwise keep the pty open after
+        // the child has exited, and the reader would never see EOF
+        drop(slave);
+        Self::new(master, child)
+    }
+
+    /// Takes ownership of a master and of a child that was already
+    /// spawned into its slave.  The slave should have been dropped.
+    pub fn new(
+        master: Box<dyn MasterPty + Send>,
+        child: Box<dyn Child + Send + Sync>,
+    ) -> Result<Self, Error> {
+        let writer = master.take_writer()?;
+        let (reader, output) = spawn_reader(&*master, "portable-pty-session-reader")?;
+        Ok(Self {
+            master: Some(master),
+            writer: Some(writer),
+            child: Some(child),
+            reader: Some(reader),
+            output,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        })
+    }
+
+    /// Sets how long shutting down waits for the output to reach EOF
+    /// after the child has exited.  Processes left behind by the child
+    /// can hold the pty open indefinitely; once the timeout elapses the
+    /// reader thread is abandoned.
+    pub fn set_drain_timeout(&mut self, timeout: Duration) {
+        self.drain_timeout = timeout;
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+
+    /// Receives the output of the pty in chunks, as it is read.
+    /// The receiver disconnects once the output reaches EOF.
+    pub fn output(&self) -> &Receiver<Vec<u8>> {
+        &self.output
+    }
+
+    pub fn master(&self) -> &dyn MasterPty {
+        self.master
+            .as_deref()
+            .expect("master is present until shutdown")
+    }
+
+    pub fn child(&self) -> &dyn Child {
+        self.child
+            .as_deref()
+            .expect("child is present until shutdown")
+    }
+
+    pub fn child_mut(&mut self) -> &mut (dyn Child + Send + Sync) {
+        self.child
+            .as_deref_mut()
+            .expect("child is present until shutdown")
+    }
+
+    /// Shuts the session down, returning how the child exited along
+    /// with any output that wasn't received from `output`
+    pub fn close(mut self) -> Result<ClosedSession, Error> {
+        self.shutdown()
+    }
+
+    /// Releases the parts of the session without shutting it down;
+    /// the child keeps running and the reader thread keeps reading
+    pub fn detach(mut self) -> SessionParts {
+        // The reader thread ends by itself once the output reaches EOF
+        drop(self.reader.take());
+        let (_, disconnected) = channel();
+        SessionParts {
+            master: self
+                .master
+                .take()
+                .expect("master is present until shutdown"),
+            writer: self
+                .writer
+                .take()
+                .expect("writer is present until shutdown"),
+            child: self.child.take().expect("child is present until shutdown"),
+            output: std::mem::replace(&mut self.output, disconnected),
+        }
+    }
+
+    fn shutdown(&mut self) -> Result<ClosedSession, Error> {
+        let mut child = self.child.take().expect("child is present until shutdown");
+        let status = match child.try_wait()? {
+            Some(status) => status,
+            None => {
+                // This follows the kill policy of the child
+                child.kill()?;
+                child.wait()?
+            }
+        };
+
+        // Close the writer before the master; on unix this sends EOF
+        // to anything else still reading the pty
+        drop(self.writer.take());
+        // On Windows the reader only sees EOF once the pseudoconsole
+        // has been closed
+        drop(self.master.take());
+
+        let (output, drained) =
+            drain_output(&self.output, Some(Instant::now() + self.drain_timeout));
+        if let Some(reader) = self.reader.take()
+            && drained
+        {
+            let _ = reader.join();
+        }
+
+        Ok(ClosedSession {
+            status,
+            output,
+            drained,
+        })
+    }
+}
+
+impl Write for PtySession {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer
+            .as_mut()
+            .expect("writer is present until shutdown")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer
+            .as_mut()
+            .expect("writer is present until shutdown")
+            .flush()
+    }
+}
+
+impl Drop for PtySession {
+    fn drop(&mut self) {
+        if self.child.is_some() {
+            let _ = self.shutdown();
+        }
+    }
+}
+
+/// Reads the output of `master` on a new thread called `name`, sending
+/// it on in chunks.  The receiver disconnects once the output reaches
+/// EOF, or fails.
+pub(crate) fn spawn_reader(
+    master: &dyn MasterPty,
+    name: &str,
+) -> Result<(JoinHandle<()>, Receiver<Vec<u8>>), Error> {
+    let mut reader = master.try_clone_reader()?;
+    let (tx, rx) = channel();
+    let thread = std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let mut buffer = [0u8; 8192];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if tx.send(buffer[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        })?;
+    Ok((thread, rx))
+}
+
+/// Collects the output from `output` until it disconnects or until
+/// `deadline`, if any, passes.  Returns the output along with whether
+/// it was drained to EOF; it isn't when other processes hold the pty
+/// open past the deadline.
+pub(crate) fn drain_output(
+    output: &Receiver<Vec<u8>>,
+    deadline: Option<Instant>,
+) -> (Vec<u8>, bool) {
+    let mut collected = vec![];
+    let drained = loop {
+        let received = match deadline {
+            Some(deadline) => {
+                output.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => output.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(chunk) => collected.extend_from_slice(&chunk),
+            Err(RecvTimeoutError::Disconnected) => break true,
+            Err(RecvTimeoutError::Timeout) => break false,
+        }
+    };
+    (collected, drained)
+}
@@ -14,8 +14,33 @@ use std::os::unix::process::CommandExt;
 use std::path::PathBuf;
 use std::{io, mem, ptr};
 
+pub use nix::sys::signal::Signal;
 pub use std::os::unix::io::RawFd;
 
+mod child;
+mod fds;
+mod kill;
+pub(crate) mod pidfd;
+#[cfg(target_os = "linux")]
+mod poller;
+mod reaper;
+mod reader;
+mod rlimit;
+pub use child::{ResourceUsage, StatusChange, UnixChild};
+pub(crate) use kill::{has_exited, is_reaped, signal_scope};
+pub use kill::{KillPolicy, KillScope, KillStep};
+#[cfg(target_os = "linux")]
+pub use poller::{Interest, PollEvent, PtyPoller, Token};
+pub use reader::{InterruptHandle, UnixMasterReader};
+pub use rlimit::{RLIM_INFINITY, Resource, ResourceLimit};
+
+#[cfg(feature = "mio")]
+mod mio_source;
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "tokio")]
+pub use async_io::{AsyncMasterReader, AsyncMasterWriter};
+
 #[derive(Default)]
 pub struct UnixPtySystem {}
 
@@ -90,6 +115,12 @@ impl std::ops::DerefMut for PtyFd {
     }
 }
 
+impl AsRawFd for PtyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
 impl Read for PtyFd {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
         match self.0.read(buf) {
@@ -225,15 +256,30 @@ impl PtyFd {
 
     fn spawn_command(&self, builder: CommandBuilder) -> anyhow::Result<std::process::Child> {
         let configured_umask = builder.umask;
+        let credentials = builder.credentials()?;
+        let mut inherit_plan = fds::InheritPlan::new(builder.get_inherited_fds())?;
+        let rlimits = builder
+            .get_rlimits()
+            .iter()
+            .map(|limit| limit.to_raw())
+            .collect::<anyhow::Result<Vec<_>>>()?;
 
         let mut cmd = builder.as_command()?;
         let controlling_tty = builder.get_controlling_tty();
+        let sweep_limit = fds::sweep_limit();
+        let hooks = builder.get_pre_exec_hooks().to_vec();
 
         unsafe {
             cmd.stdin(self.as_stdio()?)
                 .stdout(self.as_stdio()?)
                 .stderr(self.as_stdio()?)
                 .pre_exec(move || {
+                    // This runs in the child between fork and exec, where
+                    // another thread of the parent may have held the
+                    // allocator lock at the moment of the fork.  Only make
+                    // system calls here, using data prepared above;
+                    // allocating could deadlock.
+
                     // Clean up a few things before we exec the program
                     // Clear out any potentially problematic signal
                     // dispositions that we might have inherited
@@ -271,12 +317,48 @@ impl PtyFd {
                         }
                     }
 
-                    close_random_fds();
+                    // Move the inherited fds into place before the
+                    // sweep, which marks everything else close-on-exec
+                    // rather than closing it, so that std can still
+                    // report errors from the steps below
+                    inherit_plan.apply()?;
+                    fds::close_fds_except(inherit_plan.keep(), sweep_limit);
 
                     if let Some(mask) = configured_umask {
                         libc::umask(mask);
                     }
 
+                    for (resource, limit) in &rlimits {
+                        if libc::setrlimit(*resource as _, limit) == -1 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+
+                    // Drop privileges last, as the steps above may need
+                    // them.  The groups have to be changed while we are
+                    // still allowed to, so the order matters here.
+                    if let Some(creds) = &credentials {
+                        if let Some(groups) = &creds.groups
+                            && libc::setgroups(groups.len() as _, groups.as_ptr()) == -1
+                        {
+                            return Err(io::Error::last_os_error());
+                        }
+                        if let Some(gid) = creds.gid
+                            && libc::setgid(gid) == -1
+                        {
+                            return Err(io::Error::last_os_error());
+                        }
+                        if let Some(uid) = creds.uid
+                            && libc::setuid(uid) == -1
+                        {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+
+                    for hook in &hooks {
+                        hook()?;
+                    }
+
                     Ok(())
                 })
         };
@@ -298,7 +380,9 @@ impl PtyFd {
 
 /// Represents the master end of a pty.
 /// The file descriptor will be closed when the Pty is dropped.
-struct UnixMasterPty {
+/// The `MasterPty` returned by `UnixPtySystem::openpty` can be
+/// downcast to this type in order to access unix specific functionality.
+pub struct UnixMasterPty {
     fd: PtyFd,
     took_writer: RefCell<bool>,
     tty_name: Option<PathBuf>,
@@ -334,7 +418,54 @@ impl SlavePty for UnixSlavePty {
         &self,
         builder: CommandBuilder,
     ) -> Result<Box<dyn Child + Send + Sync>, Error> {
-        Ok(Box::new(self.fd.spawn_command(builder)?))
+        let kill_policy = builder.get_kill_policy().clone();
+        Ok(Box::new(UnixChild::new(
+            self.fd.spawn_command(builder)?,
+            kill_policy,
+        )))
+    }
+}
+
+impl UnixMasterPty {
+    /// Marks the writer as taken, failing if it was already taken.
+    /// Shared by the blocking and async writer accessors so that only
+    /// one of them can ever be obtained.
+    fn claim_writer(&self) -> Result<(), Error> {
+        if *self.took_writer.borrow() {
+            anyhow::bail!("cannot take writer more than once");
+        }
+        *self.took_writer.borrow_mut() = true;
+        Ok(())
+    }
+
+    /// Switches the master into or out of non-blocking mode.
+    /// In non-blocking mode the reader and writer obtained from this
+    /// master report `WouldBlock` rather than blocking.
+    /// The flag is a property of the open file description, so it
+    /// applies to every reader and writer cloned from this master,
+    /// including those that were obtained earlier.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        let fd = self.fd.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags == -1 {
+            bail!(
+                "fcntl to read flags failed: {:?}",
+                io::Error::last_os_error()
+            );
+        }
+        let flags = if nonblocking {
+            flags | libc::O_NONBLOCK
+        } else {
+            flags & !libc::O_NONBLOCK
+        };
+        let result = unsafe { libc::fcntl(fd, libc::F_SETFL, flags) };
+        if result == -1 {
+            bail!(
+                "fcntl to set O_NONBLOCK failed: {:?}",
+                io::Error::last_os_error()
+            );
+        }
+        Ok(())
     }
 }
 
@@ -353,10 +484,7 @@ impl MasterPty for UnixMasterPty {
     }
 
     fn take_writer(&self) -> Result<Box<dyn Write + Send>, Error> {
-        if *self.took_writer.borrow() {
-            anyhow::bail!("cannot take writer more than once");
-        }
-        *self.took_writer.borrow_mut() = true;
+        self.claim_writer()?;
         let fd = PtyFd(self.fd.try_clone()?);
         Ok(Box::new(UnixMasterWriter { fd }))
     }
@@ -388,17 +516,23 @@ struct UnixMasterWriter {
     fd: PtyFd,
 }
 
+/// Sends EOT to the slave side so that the process reading from it
+/// will see EOF.
+fn send_eot(fd: &mut PtyFd) {
+    let mut t: libc::termios = unsafe { std::mem::MaybeUninit::zeroed().assume_init() };
+    if unsafe { libc::tcgetattr(fd.0.as_raw_fd(), &mut t) } == 0 {
+        // EOF is only interpreted after a newline, so if it is set,
+        // we send a newline followed by EOF.
+        let eot = t.c_cc[libc::VEOF];
+        if eot != 0 {
+            let _ = fd.0.write_all(&[b'\n', eot]);
+        }
+    }
+}
+
 impl Drop for UnixMasterWriter {
     fn drop(&mut self) {
-        let mut t: libc::termios = unsafe { std::mem::MaybeUninit::zeroed().assume_init() };
-        if unsafe { libc::tcgetattr(self.fd.0.as_raw_fd(), &mut t) } == 0 {
-            // EOF is only interpreted after a newline, so if it is set,
-            // we send a newline followed by EOF.
-            let eot = t.c_cc[libc::VEOF];
-            if eot != 0 {
-                let _ = self.fd.0.write_all(&[b'\n', eot]);
-            }
-        }
+        send_eot(&mut self.fd);
     }
 }
 
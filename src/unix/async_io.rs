@@ -0,0 +1,151 @@
+//! Tokio integration for the master end of a unix pty and the
+//! child processes spawned into it.
+//!
+//! The types in this module register a duplicate of the master fd
+//! with the tokio reactor using `AsyncFd`, so that output from the
+//! slave can be consumed and input sent to it without dedicating an
+//! OS thread to blocking reads and writes.
+//!
+//! Note that the non-blocking flag is a property of the open file
+//! description rather than of an individual descriptor, so once an
+//! async reader or writer has been created, blocking readers obtained
+//! from the same master via `try_clone_reader` will also observe
+//! `WouldBlock` rather than blocking.
+use super::{send_eot, PtyFd, UnixChild, UnixMasterPty};
+use crate::{Child, ExitStatus};
+use anyhow::Error;
+use filedescriptor::FileDescriptor;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf};
+
+/// Duplicates the master fd, switches it to non-blocking mode and
+/// registers it with the reactor of the current tokio runtime.
+fn async_fd(master: &UnixMasterPty) -> Result<AsyncFd<PtyFd>, Error> {
+    let mut fd = PtyFd(master.fd.try_clone()?);
+    fd.set_non_blocking(true)?;
+    Ok(AsyncFd::new(fd)?)
+}
+
+impl UnixMasterPty {
+    /// Obtain an async readable handle; output from the slave(s) is
+    /// readable via this stream.
+    /// As with `try_clone_reader`, EIO from the underlying fd is
+    /// reported as EOF.
+    /// Must be called from within the context of a tokio runtime.
+    pub fn try_clone_async_reader(&self) -> Result<AsyncMasterReader, Error> {
+        Ok(AsyncMasterReader {
+            fd: async_fd(self)?,
+        })
+    }
+
+    /// Obtain an async writable handle; writing to it will send data
+    /// to the slave end.
+    /// Dropping the writer will send EOF to the slave end.
+    /// This shares the restriction of `take_writer`: only one writer,
+    /// blocking or async, can be taken from a given master.
+    /// Must be called from within the context of a tokio runtime.
+    pub fn take_async_writer(&self) -> Result<AsyncMasterWriter, Error> {
+        self.claim_writer()?;
+        Ok(AsyncMasterWriter {
+            fd: async_fd(self)?,
+        })
+    }
+}
+
+/// An async reader for the output of the slave end of a pty.
+/// Created by `UnixMasterPty::try_clone_async_reader`.
+pub struct AsyncMasterReader {
+    fd: AsyncFd<PtyFd>,
+}
+
+impl AsyncRead for AsyncMasterReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let mut guard = ready!(this.fd.poll_read_ready_mut(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            // PtyFd::read takes care of mapping EIO to EOF
+            match guard.try_io(|fd| fd.get_mut().read(unfilled)) {
+                Ok(Ok(size)) => {
+                    buf.advance(size);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// An async writer that sends input to the slave end of a pty.
+/// Created by `UnixMasterPty::take_async_writer`.
+/// EOT will be sent when the writer is dropped.
+pub struct AsyncMasterWriter {
+    fd: AsyncFd<PtyFd>,
+}
+
+impl Drop for AsyncMasterWriter {
+    fn drop(&mut self) {
+        send_eot(self.fd.get_mut());
+    }
+}
+
+impl AsyncWrite for AsyncMasterWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let mut guard = ready!(this.fd.poll_write_ready_mut(cx))?;
+            match guard.try_io(|fd| fd.get_mut().write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Writes go straight to the fd; there is nothing buffered here
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl UnixChild {
+    /// Wait for the child to exit without blocking the current thread.
+    /// When the child has a pidfd, it is registered with the reactor of
+    /// the current tokio runtime so that no helper thread is required.
+    /// Otherwise this falls back to the runtime agnostic `Future`
+    /// implementation of `UnixChild`.
+    pub async fn wait_async(&mut self) -> io::Result<ExitStatus> {
+        if let Some(status) = self.try_wait()? {
+            return Ok(status);
+        }
+
+        let Some(pidfd) = self.pidfd() else {
+            return (&mut *self).await.map_err(io::Error::other);
+        };
+
+        let pidfd = FileDescriptor::dup(&pidfd).map_err(io::Error::other)?;
+        let pidfd = AsyncFd::with_interest(pidfd, Interest::READABLE)?;
+        loop {
+            let mut guard = pidfd.readable().await?;
+            if let Some(status) = self.try_wait()? {
+                return Ok(status);
+            }
+            guard.clear_ready();
+        }
+    }
+}
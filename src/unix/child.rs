@@ -0,0 +1,302 @@
+//! The unix implementation of `Child`
+use super::{pidfd, reaper, signal_scope, KillPolicy, KillScope, Signal};
+use crate::{poll_until_deadline, Child, ChildKiller, ExitStatus, ProcessSignaller};
+use filedescriptor::{poll, pollfd, POLLIN};
+use anyhow::Context as _;
+#[cfg(feature = "serde_support")]
+use serde::{Deserialize, Serialize};
+use std::io::Result as IoResult;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Represents a child process spawned into a unix pty.
+///
+/// The `Child` returned by `UnixSlavePty::spawn_command` can be
+/// downcast to this type.  In addition to the blocking `Child`
+/// methods, it implements `std::future::Future` so that its exit
+/// status can be awaited from any async runtime.
+#[derive(Debug)]
+pub struct UnixChild {
+    pid: u32,
+    /// On Linux, a pidfd referencing the child; it becomes readable
+    /// when the child terminates
+    pidfd: Option<OwnedFd>,
+    status: Option<ExitStatus>,
+    rusage: Option<ResourceUsage>,
+    kill_policy: KillPolicy,
+}
+
+/// A change in the state of a child process, as reported by
+/// `UnixChild::try_wait_status_change` and
+/// `UnixChild::wait_status_change`.
+#[derive(Debug, Clone)]
+pub enum StatusChange {
+    /// The child was stopped by the given signal; it can be resumed
+    /// by sending it SIGCONT
+    Stopped(Signal),
+    /// The child was resumed after having been stopped
+    Continued,
+    /// The child terminated
+    Exited(ExitStatus),
+}
+
+/// The resources consumed by a child process and its reaped
+/// descendants, as reported by `wait4` when the child was reaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct ResourceUsage {
+    /// CPU time spent in user mode
+    pub user_time: Duration,
+    /// CPU time spent in kernel mode
+    pub system_time: Duration,
+    /// The peak resident set size, in bytes
+    pub max_rss: u64,
+    /// Page faults serviced without any I/O
+    pub minor_faults: u64,
+    /// Page faults that required I/O
+    pub major_faults: u64,
+    /// The number of times the process gave up the CPU voluntarily,
+    /// typically to wait for I/O
+    pub voluntary_context_switches: u64,
+    /// The number of times the process was preempted
+    pub involuntary_context_switches: u64,
+}
+
+impl From<&libc::rusage> for ResourceUsage {
+    fn from(usage: &libc::rusage) -> Self {
+        fn duration(tv: &libc::timeval) -> Duration {
+            Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+        }
+        // macOS reports the maximum RSS in bytes, everything else in KiB
+        let rss_scale = if cfg!(any(target_os = "macos", target_os = "ios")) {
+            1
+        } else {
+            1024
+        };
+        Self {
+            user_time: duration(&usage.ru_utime),
+            system_time: duration(&usage.ru_stime),
+            max_rss: usage.ru_maxrss as u64 * rss_scale,
+            minor_faults: usage.ru_minflt as u64,
+            major_faults: usage.ru_majflt as u64,
+            voluntary_context_switches: usage.ru_nvcsw as u64,
+            involuntary_context_switches: usage.ru_nivcsw as u64,
+        }
+    }
+}
+
+impl UnixChild {
+    pub(crate) fn new(child: std::process::Child, kill_policy: KillPolicy) -> Self {
+        let pid = child.id();
+        // We take over responsibility for reaping the child from here on,
+        // so the std::process::Child is no longer needed.  Dropping it
+        // neither kills nor waits for the process.
+        drop(child);
+        Self {
+            pid,
+            pidfd: pidfd::open(pid),
+            status: None,
+            rusage: None,
+            kill_policy,
+        }
+    }
+
+    /// Returns the pidfd associated with the child, if the system
+    /// supports them.
+    pub fn pidfd(&self) -> Option<RawFd> {
+        self.pidfd.as_ref().map(|fd| fd.as_raw_fd())
+    }
+
+    /// Returns the resources consumed by the child, once it has been
+    /// reaped by one of the wait methods.
+    pub fn resource_usage(&self) -> Option<&ResourceUsage> {
+        self.rusage.as_ref()
+    }
+
+    /// Wait for the child to exit, returning both its exit status and
+    /// the resources that it consumed.
+    pub fn wait_with_resource_usage(&mut self) -> IoResult<(ExitStatus, ResourceUsage)> {
+        let status = self.wait()?;
+        let rusage = self.rusage.expect("rusage is recorded when reaping");
+        Ok((status, rusage))
+    }
+
+    /// Poll the child for a change in its state, including being
+    /// stopped or continued by job control signals such as those
+    /// generated by Ctrl-Z.
+    /// Does not block.  Returns None if the state hasn't changed.
+    ///
+    /// A stop or continuation is reported only once; termination
+    /// is reported by this method and the `Child` wait methods alike.
+    /// Note that the child leads an orphaned process group, so the
+    /// kernel discards SIGTSTP, SIGTTIN and SIGTTOU sent to the child
+    /// itself; children that it starts in its own session, such as
+    /// jobs of an interactive shell, can still be stopped by them.
+    pub fn try_wait_status_change(&mut self) -> IoResult<Option<StatusChange>> {
+        self.wait4(libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED)
+    }
+
+    /// Blocks execution until the child is stopped, continued or
+    /// terminated, yielding the change in its state.
+    pub fn wait_status_change(&mut self) -> IoResult<StatusChange> {
+        self.wait4(libc::WUNTRACED | libc::WCONTINUED)
+            .map(|change| change.expect("blocking wait4 returned without a status"))
+    }
+
+    fn wait_for_exit(&mut self, options: libc::c_int) -> IoResult<Option<ExitStatus>> {
+        // Without WUNTRACED and WCONTINUED, wait4 reports termination only
+        match self.wait4(options)? {
+            Some(StatusChange::Exited(status)) => Ok(Some(status)),
+            _ => Ok(None),
+        }
+    }
+
+    fn wait4(&mut self, options: libc::c_int) -> IoResult<Option<StatusChange>> {
+        if let Some(status) = &self.status {
+            return Ok(Some(StatusChange::Exited(status.clone())));
+        }
+        loop {
+            let mut status: libc::c_int = 0;
+            let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+            let pid = unsafe {
+                libc::wait4(self.pid as libc::pid_t, &mut status, options, &mut rusage)
+            };
+            if pid == -1 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            if pid == 0 {
+                return Ok(None);
+            }
+            if libc::WIFSTOPPED(status) {
+                let signal = Signal::try_from(libc::WSTOPSIG(status))?;
+                return Ok(Some(StatusChange::Stopped(signal)));
+            }
+            if libc::WIFCONTINUED(status) {
+                return Ok(Some(StatusChange::Continued));
+            }
+            let status: ExitStatus = std::process::ExitStatus::from_raw(status).into();
+            self.status.replace(status.clone());
+            self.rusage.replace(ResourceUsage::from(&rusage));
+            return Ok(Some(StatusChange::Exited(status)));
+        }
+    }
+}
+
+impl Child for UnixChild {
+    fn try_wait(&mut self) -> IoResult<Option<ExitStatus>> {
+        self.wait_for_exit(libc::WNOHANG)
+    }
+
+    fn wait(&mut self) -> IoResult<ExitStatus> {
+        self.wait_for_exit(0)
+            .map(|status| status.expect("blocking wait4 returned without a status"))
+    }
+
+    fn wait_deadline(&mut self, deadline: Instant) -> IoResult<Option<ExitStatus>> {
+        let Some(pidfd) = self.pidfd() else {
+            return poll_until_deadline(self, deadline);
+        };
+        loop {
+            if let Some(status) = self.try_wait()? {
+                return Ok(Some(status));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            // The pidfd becomes readable once the child has terminated
+            let mut pfd = [pollfd {
+                fd: pidfd,
+                events: POLLIN,
+                revents: 0,
+            }];
+            match poll(&mut pfd, Some(deadline - now)) {
+                Ok(_) => {}
+                Err(filedescriptor::Error::Poll(err))
+                    if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(std::io::Error::other(err)),
+            }
+        }
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        Some(self.pid)
+    }
+}
+
+impl ChildKiller for UnixChild {
+    fn kill(&mut self) -> IoResult<()> {
+        let policy = self.kill_policy.clone();
+        self.kill_with_policy(&policy)
+    }
+
+    fn kill_with_policy(&mut self, policy: &KillPolicy) -> IoResult<()> {
+        let pid = self.pid;
+        policy.apply(self, pid, |child| matches!(child.try_wait(), Ok(Some(_))))
+    }
+
+    fn signal(&mut self, signal: Signal) -> IoResult<()> {
+        if self.status.is_some() {
+            // The pid may already have been recycled for some other process
+            return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+        }
+        let result = unsafe { libc::kill(self.pid as i32, signal as libc::c_int) };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn signal_scoped(&mut self, signal: Signal, scope: KillScope) -> IoResult<()> {
+        match scope {
+            KillScope::Process => self.signal(signal),
+            scope => {
+                let reaped = self.try_wait()?.is_some();
+                signal_scope(self.pid, signal, scope, reaped)
+            }
+        }
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(ProcessSignaller {
+            pid: self.process_id(),
+            pidfd: self.pidfd.as_ref().and_then(|fd| fd.try_clone().ok()),
+            kill_policy: self.kill_policy.clone(),
+        })
+    }
+}
+
+impl std::future::Future for UnixChild {
+    type Output = anyhow::Result<ExitStatus>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<anyhow::Result<ExitStatus>> {
+        match self.try_wait() {
+            Ok(Some(status)) => return Poll::Ready(Ok(status)),
+            Err(err) => {
+                return Poll::Ready(Err(err).context("Failed to retrieve process exit status"))
+            }
+            Ok(None) => {}
+        }
+
+        let pidfd = self.pidfd();
+        if let Err(err) = reaper::register(self.pid, pidfd, cx.waker()) {
+            return Poll::Ready(Err(err).context("Failed to watch for process exit"));
+        }
+
+        // The child may have exited between our first check and
+        // registering the waker, in which case we may have missed
+        // the notification, so check again.
+        match self.try_wait() {
+            Ok(Some(status)) => Poll::Ready(Ok(status)),
+            Err(err) => Poll::Ready(Err(err).context("Failed to retrieve process exit status")),
+            Ok(None) => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Passing file descriptors from the parent to a spawned command, and
+//! closing the rest of them
+use super::RawFd;
+use anyhow::bail;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+/// Places the descriptors requested with `CommandBuilder::inherit_fd`
+/// at their target numbers in the child.
+///
+/// Everything that needs memory is prepared in the parent by `new`, so
+/// that `apply` only makes system calls between fork and exec.
+#[derive(Debug)]
+pub(crate) struct InheritPlan {
+    /// (parent_fd, child_fd) pairs
+    mappings: Vec<(RawFd, RawFd)>,
+    /// Fds opened in the parent that must stay open until the command
+    /// has been spawned: copies of parent fds 0, 1 and 2, which std
+    /// replaces with the pty in the child before `apply` runs, and
+    /// placeholders occupying the free target fds
+    _held: Vec<OwnedFd>,
+    /// Copies of the parent fds, made in the child by `apply`
+    staged: Vec<RawFd>,
+    /// Staged copies are made at or above this number, so that they
+    /// can't collide with any of the fds in `mappings`
+    min_staged: RawFd,
+    /// The target fds, sorted, which the close sweep must leave alone
+    keep: Vec<RawFd>,
+}
+
+impl InheritPlan {
+    pub fn new(mappings: &[(RawFd, RawFd)]) -> anyhow::Result<Self> {
+        let mut keep = vec![];
+        for &(parent_fd, child_fd) in mappings {
+            if parent_fd < 0 || child_fd < 0 {
+                bail!("cannot inherit fd {parent_fd} as fd {child_fd}: fds cannot be negative");
+            }
+            if unsafe { libc::fcntl(parent_fd, libc::F_GETFD) } == -1 {
+                bail!(
+                    "cannot inherit fd {parent_fd}: {}",
+                    io::Error::last_os_error()
+                );
+            }
+            if keep.contains(&child_fd) {
+                bail!("fd {child_fd} is the target of more than one inherited fd");
+            }
+            keep.push(child_fd);
+        }
+        keep.sort_unstable();
+
+        let mut mappings = mappings.to_vec();
+        let mut held = vec![];
+        for (parent_fd, _) in mappings.iter_mut().filter(|(fd, _)| *fd <= 2) {
+            let fd = unsafe { libc::fcntl(*parent_fd, libc::F_DUPFD_CLOEXEC, 3) };
+            if fd == -1 {
+                bail!(
+                    "cannot inherit fd {parent_fd}: {}",
+                    io::Error::last_os_error()
+                );
+            }
+            let copy = unsafe { OwnedFd::from_raw_fd(fd) };
+            *parent_fd = copy.as_raw_fd();
+            held.push(copy);
+        }
+
+        // std reports a failure to spawn through a close-on-exec pipe
+        // that it opens after this, and `apply` would clobber that pipe
+        // in the child if it got one of the target numbers.  Occupy the
+        // free targets until the command has been spawned.
+        for &(parent_fd, child_fd) in &mappings {
+            if child_fd <= 2 || unsafe { libc::fcntl(child_fd, libc::F_GETFD) } != -1 {
+                continue;
+            }
+            let fd = unsafe { libc::fcntl(parent_fd, libc::F_DUPFD_CLOEXEC, child_fd) };
+            if fd == -1 {
+                bail!(
+                    "cannot reserve fd {child_fd}: {}",
+                    io::Error::last_os_error()
+                );
+            }
+            // Another thread may have taken the target in the meantime,
+            // in which case it's no longer free for std to use either
+            let placeholder = unsafe { OwnedFd::from_raw_fd(fd) };
+            if fd == child_fd {
+                held.push(placeholder);
+            }
+        }
+
+        let min_staged = mappings
+            .iter()
+            .map(|&(parent_fd, child_fd)| parent_fd.max(child_fd))
+            .max()
+            .map_or(0, |max| max + 1);
+
+        Ok(Self {
+            staged: vec![-1; mappings.len()],
+            mappings,
+            _held: held,
+            min_staged,
+            keep,
+        })
+    }
+
+    /// The fds that the child should keep open, in ascending order
+    pub fn keep(&self) -> &[RawFd] {
+        &self.keep
+    }
+
+    /// Moves the fds into place.  This is called in the child after
+    /// fork, so it must not allocate.
+    pub unsafe fn apply(&mut self) -> io::Result<()> {
+        // Copy every source out of the way first, so that moving one
+        // fd into place can't clobber the source of another.
+        // The copies are close-on-exec, so they go away by themselves.
+        for (staged, &(parent_fd, _)) in self.staged.iter_mut().zip(&self.mappings) {
+            let fd = unsafe { libc::fcntl(parent_fd, libc::F_DUPFD_CLOEXEC, self.min_staged) };
+            if fd == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            *staged = fd;
+        }
+        // dup2 clears close-on-exec on the target
+        for (&staged, &(_, child_fd)) in self.staged.iter().zip(&self.mappings) {
+            if unsafe { libc::dup2(staged, child_fd) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The upper bound used when closing fds one at a time because they
+/// can't be enumerated, for when the fd limit is unlimited or huge
+const MAX_SWEEP: RawFd = 65536;
+
+/// Returns the fd number below which `close_fds_except` closes fds
+/// when it has to close them one at a time.  This calls `getrlimit`,
+/// so it is computed in the parent.
+pub(crate) fn sweep_limit() -> RawFd {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0
+        || limit.rlim_cur == libc::RLIM_INFINITY
+    {
+        return MAX_SWEEP;
+    }
+    limit.rlim_cur.min(MAX_SWEEP as libc::rlim_t) as RawFd
+}
+
+/// Marks `fd` close-on-exec
+unsafe fn set_cloexec(fd: RawFd) {
+    unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+}
+
+/// Closes every fd numbered 3 or higher, other than those in `keep`,
+/// which must be sorted, on exec.
+///
+/// Marking the fds close-on-exec rather than closing them leaves the
+/// pipe that std uses to report a failure to spawn intact, so that
+/// errors from later steps still reach the parent.
+///
+/// This is called in the child after fork, so it only makes system
+/// calls: `close_range(2)` on Linux 5.11 and later, otherwise reading
+/// `/proc/self/fd` with `getdents64(2)` into a buffer on the stack, and
+/// as a last resort visiting each fd below `limit` in turn.
+pub(crate) unsafe fn close_fds_except(keep: &[RawFd], limit: RawFd) {
+    #[cfg(target_os = "linux")]
+    {
+        if unsafe { close_ranges(keep) } || unsafe { close_listed(keep) } {
+            return;
+        }
+    }
+
+    let mut keep = keep.iter().peekable();
+    for fd in 3..limit {
+        while keep.next_if(|&&kept| kept < fd).is_some() {}
+        if keep.next_if_eq(&&fd).is_none() {
+            unsafe { set_cloexec(fd) };
+        }
+    }
+}
+
+/// Closes the gaps between the kept fds with `close_range(2)`.
+/// Returns false if the kernel doesn't support it.
+#[cfg(target_os = "linux")]
+unsafe fn close_ranges(keep: &[RawFd]) -> bool {
+    let close_range = |first: RawFd, last: libc::c_uint| unsafe {
+        libc::syscall(
+            libc::SYS_close_range,
+            first as libc::c_uint,
+            last,
+            libc::CLOSE_RANGE_CLOEXEC,
+        ) == 0
+    };
+    let mut first = 3;
+    for &kept in keep {
+        if kept >= first {
+            if kept > first && !close_range(first, (kept - 1) as libc::c_uint) {
+                return false;
+            }
+            first = kept + 1;
+        }
+    }
+    close_range(first, libc::c_uint::MAX)
+}
+
+/// Closes the fds listed in `/proc/self/fd`.
+/// Returns false if the directory can't be read.
+#[cfg(target_os = "linux")]
+unsafe fn close_listed(keep: &[RawFd]) -> bool {
+    let dir = unsafe {
+        libc::open(
+            c"/proc/self/fd".as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        )
+    };
+    if dir == -1 {
+        return false;
+    }
+
+    // Aligned for the dirent64 records that the kernel fills it with
+    let mut buffer = [0u64; 512];
+    loop {
+        let len = unsafe {
+            libc::syscall(
+                libc::SYS_getdents64,
+                dir,
+                buffer.as_mut_ptr(),
+                std::mem::size_of_val(&buffer),
+            )
+        };
+        if len <= 0 {
+            break;
+        }
+        let bytes =
+            unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, len as usize) };
+        let mut offset = 0;
+        while offset < bytes.len() {
+            // struct linux_dirent64 { u64 d_ino; i64 d_off; u16 d_reclen;
+            // u8 d_type; char d_name[]; }
+            let reclen = u16::from_ne_bytes([bytes[offset + 16], bytes[offset + 17]]) as usize;
+            let name = &bytes[offset + 19..offset + reclen];
+            if let Some(fd) = parse_fd(name)
+                && fd > 2
+                && fd != dir
+                && keep.binary_search(&fd).is_err()
+            {
+                unsafe { set_cloexec(fd) };
+            }
+            offset += reclen;
+        }
+    }
+    unsafe { libc::close(dir) };
+    true
+}
+
+/// Parses a NUL terminated decimal fd number without allocating
+#[cfg(target_os = "linux")]
+fn parse_fd(name: &[u8]) -> Option<RawFd> {
+    let mut fd: RawFd = 0;
+    let mut digits = 0;
+    for &byte in name.iter().take_while(|&&byte| byte != 0) {
+        if !byte.is_ascii_digit() {
+            return None;
+        }
+        fd = fd.checked_mul(10)?.checked_add((byte - b'0') as RawFd)?;
+        digits += 1;
+    }
+    (digits > 0).then_some(fd)
+}
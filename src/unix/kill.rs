@@ -0,0 +1,328 @@
+//! Configurable escalation when terminating a child process
+use super::Signal;
+use crate::ChildKiller;
+#[cfg(feature = "serde_support")]
+use serde::{Deserialize, Serialize};
+use std::io::Result as IoResult;
+use std::time::{Duration, Instant};
+
+/// How often to check whether the child has terminated while waiting
+/// for a step of the policy to take effect.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Selects which processes are signalled by a `KillPolicy` or by
+/// `ChildKiller::signal_scoped`.
+///
+/// Children spawned into a pty are made into session leaders, so
+/// everything started by them (background jobs, pipelines and so on)
+/// lives in their session, and, unless job control moved it elsewhere,
+/// in their process group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub enum KillScope {
+    /// Only the child process itself
+    #[default]
+    Process,
+    /// Every process in the process group led by the child
+    ProcessGroup,
+    /// Every process in the session led by the child.
+    /// This requires `/proc` to enumerate the processes in the session.
+    Session,
+}
+
+/// A single step of a `KillPolicy`: the signal to send and how long
+/// to give the child to terminate before moving on to the next step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct KillStep {
+    #[cfg_attr(feature = "serde_support", serde(with = "signal_name"))]
+    pub signal: Signal,
+    pub timeout: Duration,
+}
+
+/// Describes how `ChildKiller::kill` terminates a child process.
+///
+/// Each step sends its signal and then waits up to its timeout for the
+/// child to terminate; if the child is still running once the timeout
+/// has elapsed, the next step is attempted.
+///
+/// When the scope is wider than the child process itself, each signal
+/// is sent to all of the processes in the scope, and the policy runs
+/// until the child has terminated and no other processes remain in
+/// the scope.
+///
+/// The default policy sends SIGHUP, which is what a process would see
+/// if its terminal went away, waits for up to 200ms, and then sends
+/// SIGKILL, to the child process only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct KillPolicy {
+    steps: Vec<KillStep>,
+    #[cfg_attr(feature = "serde_support", serde(default))]
+    scope: KillScope,
+}
+
+impl Default for KillPolicy {
+    fn default() -> Self {
+        let mut policy = Self::new();
+        policy.add_step(Signal::SIGHUP, Duration::from_millis(200));
+        policy.add_step(Signal::SIGKILL, Duration::ZERO);
+        policy
+    }
+}
+
+impl KillPolicy {
+    /// Create a policy with no steps.  Steps are added with `add_step`.
+    /// A policy without any steps doesn't send any signals.
+    pub fn new() -> Self {
+        Self {
+            steps: vec![],
+            scope: KillScope::Process,
+        }
+    }
+
+    /// Append a step that sends `signal` and then waits up to `timeout`
+    /// for the child to terminate.
+    pub fn add_step(&mut self, signal: Signal, timeout: Duration) {
+        self.steps.push(KillStep { signal, timeout });
+    }
+
+    pub fn steps(&self) -> &[KillStep] {
+        &self.steps
+    }
+
+    /// Set which processes are signalled by each step
+    pub fn set_scope(&mut self, scope: KillScope) {
+        self.scope = scope;
+    }
+
+    pub fn scope(&self) -> KillScope {
+        self.scope
+    }
+
+    /// Carries out the policy against `target`, whose process id is
+    /// `pid`.  Signals are delivered with `ChildKiller::signal_scoped`
+    /// and `exited` returns true once the child has terminated.
+    pub(crate) fn apply<T: ChildKiller + ?Sized>(
+        &self,
+        target: &mut T,
+        pid: u32,
+        exited: impl Fn(&mut T) -> bool,
+    ) -> IoResult<()> {
+        let scope = self.scope;
+        let send = |target: &mut T, signal| target.signal_scoped(signal, scope);
+        let exited = |target: &mut T| exited(target) && scope_is_empty(pid, scope);
+
+        for (idx, step) in self.steps.iter().enumerate() {
+            if exited(target) {
+                return Ok(());
+            }
+
+            if let Err(err) = send(target, step.signal) {
+                // If the child went away after an earlier step had been
+                // delivered, then the policy did its job.
+                if idx > 0 && err.raw_os_error() == Some(libc::ESRCH) {
+                    return Ok(());
+                }
+                return Err(err);
+            }
+
+            let deadline = Instant::now() + step.timeout;
+            loop {
+                if exited(target) {
+                    return Ok(());
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                std::thread::sleep((deadline - now).min(POLL_INTERVAL));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the child process `pid` has terminated, without
+/// reaping it, so that a thread blocked in `wait` still receives the
+/// exit status.
+/// A pid that is no longer our child has already been reaped and is
+/// also reported as having terminated.
+pub(crate) fn has_exited(pid: u32) -> bool {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let result = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            pid as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    if result != 0 {
+        return std::io::Error::last_os_error().raw_os_error() == Some(libc::ECHILD);
+    }
+    // With WNOHANG, si_pid is left as zero if the child is still running
+    unsafe { info.si_pid() != 0 }
+}
+
+/// Returns true if the child process `pid` has been reaped, either by
+/// us or by a thread that is blocked in `wait`
+pub(crate) fn is_reaped(pid: u32) -> bool {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let result = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            pid as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    result != 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::ECHILD)
+}
+
+/// Sends `signal` to the processes in `scope` relative to the session
+/// leader `pid`.  The child itself should be signalled with
+/// `ChildKiller::signal`, which can use its pidfd.
+///
+/// The process group and session are addressed by the id of the child,
+/// which the kernel doesn't reuse while the child is a zombie or while
+/// anything remains in its group or session.  Once the child has been
+/// reaped, which the caller reports with `reaped`, a process that has
+/// its pid is unrelated, so ESRCH is returned rather than signalling
+/// whatever group or session that process might lead.
+pub(crate) fn signal_scope(
+    pid: u32,
+    signal: Signal,
+    scope: KillScope,
+    reaped: bool,
+) -> IoResult<()> {
+    let pid = pid as libc::pid_t;
+    if reaped
+        && (unsafe { libc::kill(pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH))
+    {
+        return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+    }
+    let result = match scope {
+        KillScope::Process => unsafe { libc::kill(pid, signal as libc::c_int) },
+        // The child is a session leader, so it is also the leader
+        // of a process group with the same id
+        KillScope::ProcessGroup => unsafe { libc::killpg(pid, signal as libc::c_int) },
+        KillScope::Session => {
+            let mut delivered = false;
+            for member in scope_members(pid, Field::Session)? {
+                if unsafe { libc::kill(member.pid, signal as libc::c_int) } == 0 {
+                    delivered = true;
+                }
+            }
+            if delivered {
+                0
+            } else {
+                return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+            }
+        }
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns true if there are no longer any running processes in `scope`
+/// relative to the session leader `pid`.  The child process itself is
+/// accounted for by the caller, so this is always true for
+/// `KillScope::Process`.
+fn scope_is_empty(pid: u32, scope: KillScope) -> bool {
+    let pid = pid as libc::pid_t;
+    let field = match scope {
+        KillScope::Process => return true,
+        KillScope::ProcessGroup => Field::ProcessGroup,
+        KillScope::Session => Field::Session,
+    };
+    match scope_members(pid, field) {
+        Ok(members) => members.iter().all(|member| member.state == 'Z'),
+        Err(_) if scope == KillScope::ProcessGroup => {
+            // Without /proc we can't tell zombies apart from running
+            // processes, but can at least tell when the group is gone
+            let result = unsafe { libc::killpg(pid, 0) };
+            result != 0
+        }
+        Err(_) => true,
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    ProcessGroup,
+    Session,
+}
+
+struct Member {
+    pid: libc::pid_t,
+    state: char,
+}
+
+/// Walks `/proc` to find the processes whose process group or session
+/// id matches `id`.
+fn scope_members(id: libc::pid_t, field: Field) -> IoResult<Vec<Member>> {
+    let dir = std::fs::read_dir("/proc").map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("unable to enumerate processes via /proc: {err:#}"),
+        )
+    })?;
+
+    let mut members = vec![];
+    for entry in dir.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<libc::pid_t>().ok())
+        else {
+            continue;
+        };
+        // The process may have gone away since we listed the directory
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        // The command name is parenthesized and may itself contain
+        // spaces and parentheses, so skip past the last `)` before
+        // splitting up the remaining fields:
+        // `pid (comm) state ppid pgrp session ...`
+        let Some((_, fields)) = stat.rsplit_once(')') else {
+            continue;
+        };
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        let (Some(state), Some(pgrp), Some(session)) = (
+            fields.first().and_then(|s| s.chars().next()),
+            fields.get(2).and_then(|s| s.parse::<libc::pid_t>().ok()),
+            fields.get(3).and_then(|s| s.parse::<libc::pid_t>().ok()),
+        ) else {
+            continue;
+        };
+        let value = match field {
+            Field::ProcessGroup => pgrp,
+            Field::Session => session,
+        };
+        if value == id {
+            members.push(Member { pid, state });
+        }
+    }
+    Ok(members)
+}
+
+#[cfg(feature = "serde_support")]
+mod signal_name {
+    use super::Signal;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(signal: &Signal, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(signal.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Signal, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Signal::from_str(&name).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,42 @@
+//! mio integration for the master end of a unix pty.
+//!
+//! `UnixMasterPty` implements `mio::event::Source`, so that it can be
+//! registered with a `mio::Poll` directly.  Once it has been reported
+//! as ready, the reader from `try_clone_reader` and the writer from
+//! `take_writer` can be used until they report `WouldBlock`.
+use super::UnixMasterPty;
+use mio::event::Source;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/// Registering switches the master into non-blocking mode, because mio
+/// delivers edge-triggered events: a blocking read or write after the
+/// readiness has been consumed would otherwise stall the event loop.
+/// See `UnixMasterPty::set_nonblocking` for what that implies for
+/// other readers and writers of the same master.
+impl Source for UnixMasterPty {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.set_nonblocking(true).map_err(io::Error::other)?;
+        SourceFd(&self.fd.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.fd.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.fd.as_raw_fd()).deregister(registry)
+    }
+}
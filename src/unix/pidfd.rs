@@ -0,0 +1,93 @@
+//! Helpers for working with Linux pidfds.
+//!
+//! A pidfd refers to a specific process rather than to a process id,
+//! so unlike a numeric pid it can't end up referring to an unrelated
+//! process once the original has been reaped and its pid reused.
+//! On other systems, and on kernels older than 5.3, `open` returns
+//! None and callers fall back to using the pid.
+use super::Signal;
+use filedescriptor::{poll, pollfd, POLLIN};
+use std::io::Result as IoResult;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::FromRawFd;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+/// Open a pidfd for `pid`
+#[cfg(target_os = "linux")]
+pub(crate) fn open(pid: u32) -> Option<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd < 0 {
+        // Most likely ENOSYS on kernels older than 5.3
+        log::trace!(
+            "pidfd_open({pid}) failed: {:?}",
+            std::io::Error::last_os_error()
+        );
+        None
+    } else {
+        Some(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn open(_pid: u32) -> Option<OwnedFd> {
+    None
+}
+
+/// Send `signal` to the process referenced by `pidfd`.
+/// Fails with ESRCH once the process has been reaped.
+#[cfg(target_os = "linux")]
+pub(crate) fn send_signal(pidfd: &OwnedFd, signal: Signal) -> IoResult<()> {
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_pidfd_send_signal,
+            pidfd.as_raw_fd(),
+            signal as libc::c_int,
+            std::ptr::null::<libc::siginfo_t>(),
+            0,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn send_signal(_pidfd: &OwnedFd, _signal: Signal) -> IoResult<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+/// Returns true if the process referenced by `pidfd` has been reaped.
+/// A process that has terminated but is still a zombie can be signalled,
+/// so a probe with the null signal only fails once it has been reaped.
+#[cfg(target_os = "linux")]
+pub(crate) fn is_reaped(pidfd: &OwnedFd) -> bool {
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_pidfd_send_signal,
+            pidfd.as_raw_fd(),
+            0,
+            std::ptr::null::<libc::siginfo_t>(),
+            0,
+        )
+    };
+    result != 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn is_reaped(_pidfd: &OwnedFd) -> bool {
+    false
+}
+
+/// Returns true if the process referenced by `pidfd` has terminated,
+/// whether or not it has been reaped.
+pub(crate) fn has_exited(pidfd: &OwnedFd) -> bool {
+    // The pidfd becomes readable once the process has terminated
+    let mut pfd = [pollfd {
+        fd: pidfd.as_raw_fd(),
+        events: POLLIN,
+        revents: 0,
+    }];
+    matches!(poll(&mut pfd, Some(Duration::ZERO)), Ok(n) if n > 0)
+}
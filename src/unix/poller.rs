@@ -0,0 +1,293 @@
+//! Servicing many ptys from a single thread.
+//!
+//! `PtyPoller` wraps an epoll instance.  Masters are registered by
+//! their file descriptor and children by a pidfd, so that one thread
+//! can wait for any of them to become readable, writable or to exit,
+//! rather than dedicating a blocking reader thread to each pty.
+use super::{RawFd, UnixChild, pidfd};
+use crate::{Child, MasterPty};
+use anyhow::{Context as _, Error, anyhow, bail};
+use std::collections::HashMap;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Set in the epoll data of child registrations, to tell them apart
+/// from fds registered with the same token
+const CHILD_BIT: u64 = 1 << 63;
+
+/// Identifies a registration with a `PtyPoller`; chosen by the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Token(pub usize);
+
+/// The readiness that a registered fd is polled for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interest {
+    pub readable: bool,
+    pub writable: bool,
+}
+
+impl Interest {
+    pub const READABLE: Self = Self {
+        readable: true,
+        writable: false,
+    };
+    pub const WRITABLE: Self = Self {
+        readable: false,
+        writable: true,
+    };
+    pub const BOTH: Self = Self {
+        readable: true,
+        writable: true,
+    };
+
+    fn epoll_events(self) -> u32 {
+        let mut events = 0;
+        if self.readable {
+            events |= libc::EPOLLIN;
+        }
+        if self.writable {
+            events |= libc::EPOLLOUT;
+        }
+        events as u32
+    }
+}
+
+/// An event delivered by `PtyPoller::poll`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollEvent {
+    /// The fd can be read without blocking.  This is also reported
+    /// when the slave side has been closed, in which case reading
+    /// returns EOF.
+    Readable(Token),
+    /// The fd can be written without blocking
+    Writable(Token),
+    /// The child has exited and can be waited for without blocking
+    Exited(Token),
+}
+
+/// Waits for events on many ptys and children at once.
+/// Registered fds are level triggered: they are reported by each call
+/// to `poll` for as long as they remain ready.  Each child is reported
+/// as exited once.
+///
+/// ```no_run
+/// use portable_pty::unix::{Interest, PollEvent, PtyPoller, Token};
+/// use portable_pty::{CommandBuilder, PtySize, native_pty_system};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let pair = native_pty_system().openpty(PtySize::default())?;
+/// let mut child = pair.slave.spawn_command(CommandBuilder::new("ls"))?;
+/// drop(pair.slave);
+/// let mut reader = pair.master.try_clone_reader()?;
+///
+/// let poller = PtyPoller::new()?;
+/// poller.register_master(&*pair.master, Token(0), Interest::READABLE)?;
+/// poller.register_child(&*child, Token(0))?;
+///
+/// let mut events = vec![];
+/// loop {
+///     poller.poll(&mut events, None)?;
+///     for event in &events {
+///         match event {
+///             PollEvent::Readable(_) => { /* read from reader */ }
+///             PollEvent::Writable(_) => {}
+///             PollEvent::Exited(token) => {
+///                 child.wait()?;
+///                 poller.deregister_child(*token)?;
+///             }
+///         }
+///     }
+/// #   break;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PtyPoller {
+    epoll: OwnedFd,
+    /// The pidfds of registered children
+    children: Mutex<HashMap<Token, OwnedFd>>,
+}
+
+impl PtyPoller {
+    pub fn new() -> Result<Self, Error> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("epoll_create1");
+        }
+        Ok(Self {
+            epoll: unsafe { OwnedFd::from_raw_fd(fd) },
+            children: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn ctl(&self, op: libc::c_int, fd: RawFd, events: u32, data: u64) -> std::io::Result<()> {
+        let mut event = libc::epoll_event { events, u64: data };
+        let result = unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), op, fd, &mut event) };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn check_token(token: Token) -> Result<u64, Error> {
+        let data = token.0 as u64;
+        if data & CHILD_BIT != 0 {
+            bail!("token {} is too large", token.0);
+        }
+        Ok(data)
+    }
+
+    /// Registers an arbitrary fd, such as an eventfd used to wake the
+    /// polling thread.  The fd must remain open until it is deregistered.
+    pub fn register_fd(&self, fd: RawFd, token: Token, interest: Interest) -> Result<(), Error> {
+        let data = Self::check_token(token)?;
+        self.ctl(libc::EPOLL_CTL_ADD, fd, interest.epoll_events(), data)
+            .with_context(|| format!("registering fd {fd}"))
+    }
+
+    /// Changes the token or interest of a registered fd
+    pub fn modify_fd(&self, fd: RawFd, token: Token, interest: Interest) -> Result<(), Error> {
+        let data = Self::check_token(token)?;
+        self.ctl(libc::EPOLL_CTL_MOD, fd, interest.epoll_events(), data)
+            .with_context(|| format!("modifying fd {fd}"))
+    }
+
+    pub fn deregister_fd(&self, fd: RawFd) -> Result<(), Error> {
+        self.ctl(libc::EPOLL_CTL_DEL, fd, 0, 0)
+            .with_context(|| format!("deregistering fd {fd}"))
+    }
+
+    fn master_fd(master: &dyn MasterPty) -> Result<RawFd, Error> {
+        master
+            .as_raw_fd()
+            .ok_or_else(|| anyhow!("this kind of master has no file descriptor to poll"))
+    }
+
+    /// Registers the fd of `master`.  The master, or a reader or writer
+    /// cloned from it, can then be used without blocking when it is
+    /// reported as ready.  The master must be deregistered before it
+    /// is dropped.
+    pub fn register_master(
+        &self,
+        master: &dyn MasterPty,
+        token: Token,
+        interest: Interest,
+    ) -> Result<(), Error> {
+        self.register_fd(Self::master_fd(master)?, token, interest)
+    }
+
+    /// Changes the token or interest of a registered master
+    pub fn modify_master(
+        &self,
+        master: &dyn MasterPty,
+        token: Token,
+        interest: Interest,
+    ) -> Result<(), Error> {
+        self.modify_fd(Self::master_fd(master)?, token, interest)
+    }
+
+    pub fn deregister_master(&self, master: &dyn MasterPty) -> Result<(), Error> {
+        self.deregister_fd(Self::master_fd(master)?)
+    }
+
+    /// Registers `child` so that `PollEvent::Exited` is delivered once
+    /// it has exited.  This requires pidfd support, which was added in
+    /// Linux 5.3.  Other than a `UnixChild`, which holds on to its
+    /// pidfd, the child must not have been waited for yet.
+    pub fn register_child(&self, child: &dyn Child, token: Token) -> Result<(), Error> {
+        let data = Self::check_token(token)? | CHILD_BIT;
+        let mut children = self.children.lock().unwrap();
+        if children.contains_key(&token) {
+            bail!("a child is already registered with token {}", token.0);
+        }
+        let pid = child
+            .process_id()
+            .ok_or_else(|| anyhow!("child has no process id"))?;
+        // A pid can be reused once the child has been reaped, so prefer
+        // the pidfd that the child opened when it was spawned
+        let pidfd = match child.downcast_ref::<UnixChild>().and_then(UnixChild::pidfd) {
+            Some(fd) => unsafe { BorrowedFd::borrow_raw(fd) }
+                .try_clone_to_owned()
+                .with_context(|| format!("duplicating the pidfd of process {pid}"))?,
+            None => pidfd::open(pid).ok_or_else(|| {
+                anyhow!("unable to open a pidfd for process {pid}; pidfds need Linux 5.3 or later")
+            })?,
+        };
+        let events = (libc::EPOLLIN | libc::EPOLLONESHOT) as u32;
+        self.ctl(libc::EPOLL_CTL_ADD, pidfd.as_raw_fd(), events, data)
+            .with_context(|| format!("registering process {pid}"))?;
+        children.insert(token, pidfd);
+        Ok(())
+    }
+
+    /// Removes a child registration, typically once the child has
+    /// been waited for after being reported as exited
+    pub fn deregister_child(&self, token: Token) -> Result<(), Error> {
+        let pidfd = self
+            .children
+            .lock()
+            .unwrap()
+            .remove(&token)
+            .ok_or_else(|| anyhow!("no child is registered with token {}", token.0))?;
+        // Closing the pidfd removes it from the epoll set
+        drop(pidfd);
+        Ok(())
+    }
+
+    /// Waits for events on the registered fds and children, for up to
+    /// `timeout` or indefinitely if it is None.  `events` is cleared and
+    /// then filled with the events that occurred, and their number is
+    /// returned; being interrupted by a signal yields no events.
+    pub fn poll(
+        &self,
+        events: &mut Vec<PollEvent>,
+        timeout: Option<Duration>,
+    ) -> Result<usize, Error> {
+        events.clear();
+        let timeout_ms = match timeout {
+            // Round up so that short timeouts don't become busy loops
+            Some(timeout) => timeout
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+
+        const MAX_EVENTS: usize = 256;
+        let mut ready = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let count = unsafe {
+            libc::epoll_wait(
+                self.epoll.as_raw_fd(),
+                ready.as_mut_ptr(),
+                MAX_EVENTS as libc::c_int,
+                timeout_ms,
+            )
+        };
+        if count < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(0);
+            }
+            return Err(err).context("epoll_wait");
+        }
+
+        for event in &ready[..count as usize] {
+            let data = event.u64;
+            let flags = event.events as libc::c_int;
+            if data & CHILD_BIT != 0 {
+                events.push(PollEvent::Exited(Token((data & !CHILD_BIT) as usize)));
+                continue;
+            }
+            let token = Token(data as usize);
+            if flags & (libc::EPOLLIN | libc::EPOLLHUP | libc::EPOLLERR) != 0 {
+                events.push(PollEvent::Readable(token));
+            }
+            if flags & libc::EPOLLOUT != 0 {
+                events.push(PollEvent::Writable(token));
+            }
+        }
+        Ok(events.len())
+    }
+}
@@ -0,0 +1,196 @@
+//! A reader for the master end of a pty that doesn't have to block
+//! forever.
+//!
+//! The reader returned by `MasterPty::try_clone_reader` blocks until
+//! the child produces output or the pty is closed, which leaves a
+//! reading thread stuck if the child hangs.  `UnixMasterReader` polls
+//! the master together with the read end of a pipe, which allows it to
+//! time out, to behave as though it were non-blocking, and to be woken
+//! up by an `InterruptHandle` from another thread.
+use super::{PtyFd, UnixMasterPty};
+use anyhow::Error;
+use filedescriptor::{FileDescriptor, POLLIN, Pipe, poll, pollfd};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+impl UnixMasterPty {
+    /// Obtain a reader for the output of the slave(s) that supports
+    /// timeouts, non-blocking reads and being interrupted.
+    pub fn try_clone_master_reader(&self) -> Result<UnixMasterReader, Error> {
+        let Pipe { read, write } = Pipe::new()?;
+        Ok(UnixMasterReader {
+            fd: PtyFd(self.fd.try_clone()?),
+            interrupt_fd: read,
+            interrupt: InterruptHandle {
+                inner: Arc::new(Interrupt {
+                    fired: AtomicBool::new(false),
+                    fd: Mutex::new(write),
+                }),
+            },
+            timeout: None,
+            nonblocking: false,
+        })
+    }
+}
+
+/// A reader for the output of the slave end of a pty.
+/// Created by `UnixMasterPty::try_clone_master_reader`.
+///
+/// Reads report `TimedOut` once the read timeout elapses without any
+/// output, and `WouldBlock` in non-blocking mode if no output is
+/// available.  Once the reader has been interrupted, reads return EOF.
+///
+/// Readiness is checked before reading, so if the same master is read
+/// from elsewhere at the same time, that other reader can consume the
+/// output first and the read here will block until more arrives.
+pub struct UnixMasterReader {
+    fd: PtyFd,
+    interrupt_fd: FileDescriptor,
+    interrupt: InterruptHandle,
+    timeout: Option<Duration>,
+    nonblocking: bool,
+}
+
+struct Interrupt {
+    fired: AtomicBool,
+    /// The write end of the pipe polled by the reader
+    fd: Mutex<FileDescriptor>,
+}
+
+/// Wakes up a `UnixMasterReader` from another thread.
+/// Obtained from `UnixMasterReader::interrupt_handle`.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    inner: Arc<Interrupt>,
+}
+
+impl std::fmt::Debug for InterruptHandle {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("InterruptHandle")
+            .field("interrupted", &self.is_interrupted())
+            .finish()
+    }
+}
+
+impl InterruptHandle {
+    /// Interrupts the reader: a read that is blocked returns EOF, as do
+    /// all subsequent reads.  This is intended for shutting down the
+    /// thread that is reading, and can't be undone.
+    pub fn interrupt(&self) {
+        if self.inner.fired.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // The byte is never consumed, so the pipe stays readable and
+        // every later poll sees the interruption too
+        let _ = self.inner.fd.lock().unwrap().write_all(b"x");
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.inner.fired.load(Ordering::SeqCst)
+    }
+}
+
+impl std::fmt::Debug for UnixMasterReader {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("UnixMasterReader")
+            .field("fd", &self.fd.as_raw_fd())
+            .field("timeout", &self.timeout)
+            .field("nonblocking", &self.nonblocking)
+            .field("interrupted", &self.interrupt.is_interrupted())
+            .finish()
+    }
+}
+
+impl UnixMasterReader {
+    /// Sets how long a read waits for output before failing with
+    /// `TimedOut`; None, the default, waits indefinitely.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// In non-blocking mode a read fails with `WouldBlock` when no
+    /// output is available.  Unlike `UnixMasterPty::set_nonblocking`,
+    /// this only affects this reader.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.nonblocking = nonblocking;
+    }
+
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblocking
+    }
+
+    /// Returns a handle that can interrupt this reader from another thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Waits until the master is readable.  Returns false if the reader
+    /// was interrupted.
+    fn wait_readable(&self) -> io::Result<bool> {
+        let deadline = match (self.nonblocking, self.timeout) {
+            (true, _) => Some(Instant::now()),
+            (false, Some(timeout)) => Some(Instant::now() + timeout),
+            (false, None) => None,
+        };
+        loop {
+            let mut poll_array = [
+                pollfd {
+                    fd: self.fd.as_raw_fd(),
+                    events: POLLIN,
+                    revents: 0,
+                },
+                pollfd {
+                    fd: self.interrupt_fd.as_raw_fd(),
+                    events: POLLIN,
+                    revents: 0,
+                },
+            ];
+            let timeout =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            match poll(&mut poll_array, timeout) {
+                Ok(_) => {}
+                Err(filedescriptor::Error::Poll(err))
+                    if err.kind() == io::ErrorKind::Interrupted =>
+                {
+                    continue;
+                }
+                Err(err) => return Err(io::Error::other(err)),
+            }
+
+            if poll_array[1].revents != 0 || self.interrupt.is_interrupted() {
+                return Ok(false);
+            }
+            // A hangup is reported for a closed slave; the read then
+            // sees EIO, which is reported as EOF
+            if poll_array[0].revents != 0 {
+                return Ok(true);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(if self.nonblocking {
+                    io::Error::from(io::ErrorKind::WouldBlock)
+                } else {
+                    io::Error::new(io::ErrorKind::TimedOut, "timed out reading from pty")
+                });
+            }
+        }
+    }
+}
+
+impl Read for UnixMasterReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.wait_readable()? {
+            return Ok(0);
+        }
+        self.fd.read(buf)
+    }
+}
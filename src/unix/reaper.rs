@@ -0,0 +1,278 @@
+//! A process wide helper thread that wakes up futures waiting for
+//! child processes to exit.
+//!
+//! On Linux the child is represented by a pidfd which becomes readable
+//! when the process terminates, so the helper thread simply polls the
+//! set of registered pidfds.
+//! Where pidfd is not available we fall back to installing a SIGCHLD
+//! handler which pokes the helper thread through a self-pipe; since
+//! SIGCHLD doesn't tell us which child changed state, every waiter
+//! that has no pidfd is woken and is expected to re-check its status
+//! with a non-blocking wait.
+use filedescriptor::{poll, pollfd, FileDescriptor, Pipe, POLLIN};
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::task::Waker;
+
+/// Written to the self-pipe when a new waiter is registered, so that
+/// the helper thread rebuilds its poll set.
+const REGISTERED: u8 = b'R';
+/// Written to the self-pipe by the SIGCHLD handler.
+const SIGCHLD: u8 = b'C';
+
+struct Waiter {
+    pid: u32,
+    /// A duplicate of the child's pidfd, owned by the waiter
+    pidfd: Option<FileDescriptor>,
+    waker: Waker,
+}
+
+struct Reaper {
+    waiters: Mutex<Vec<Waiter>>,
+    notify: Mutex<FileDescriptor>,
+}
+
+static REAPER: OnceLock<Reaper> = OnceLock::new();
+
+/// The write end of the self-pipe, for use by the signal handler
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+static PREVIOUS_HANDLER: OnceLock<libc::sigaction> = OnceLock::new();
+/// The outcome of installing the SIGCHLD handler, as an errno, which
+/// is reported to every waiter that depends on the handler
+static HANDLER_INSTALLED: OnceLock<Result<(), i32>> = OnceLock::new();
+
+fn reaper() -> &'static Reaper {
+    REAPER.get_or_init(|| {
+        let Pipe { read, mut write } = Pipe::new().expect("failed to create reaper pipe");
+        // The signal handler must never block
+        write
+            .set_non_blocking(true)
+            .expect("failed to make reaper pipe non-blocking");
+        SIGNAL_PIPE.store(write.as_raw_fd(), Ordering::SeqCst);
+
+        std::thread::Builder::new()
+            .name("portable-pty-reaper".to_string())
+            .spawn(move || run(read))
+            .expect("failed to spawn reaper thread");
+
+        Reaper {
+            waiters: Mutex::new(vec![]),
+            notify: Mutex::new(write),
+        }
+    })
+}
+
+/// Arrange for `waker` to be woken when the child identified by `pid`
+/// (and `pidfd`, if available) exits.
+/// Registering again for the same pid replaces the previous waker.
+pub(crate) fn register(pid: u32, pidfd: Option<RawFd>, waker: &Waker) -> std::io::Result<()> {
+    let reaper = reaper();
+    if pidfd.is_none() {
+        install_sigchld_handler()?;
+    }
+
+    {
+        let mut waiters = reaper.waiters.lock().unwrap();
+        if let Some(waiter) = waiters.iter_mut().find(|w| w.pid == pid) {
+            waiter.waker.clone_from(waker);
+            return Ok(());
+        }
+        let pidfd = match pidfd {
+            Some(fd) => Some(FileDescriptor::dup(&fd).map_err(std::io::Error::other)?),
+            None => None,
+        };
+        waiters.push(Waiter {
+            pid,
+            pidfd,
+            waker: waker.clone(),
+        });
+    }
+
+    let _ = reaper.notify.lock().unwrap().write(&[REGISTERED]);
+    Ok(())
+}
+
+fn run(mut read: FileDescriptor) {
+    let reaper = reaper();
+    let mut buf = [0u8; 64];
+    loop {
+        let mut pfds = vec![pollfd {
+            fd: read.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        }];
+        let pids: Vec<u32> = {
+            let waiters = reaper.waiters.lock().unwrap();
+            waiters
+                .iter()
+                .filter_map(|w| {
+                    let fd = w.pidfd.as_ref()?;
+                    pfds.push(pollfd {
+                        fd: fd.as_raw_fd(),
+                        events: POLLIN,
+                        revents: 0,
+                    });
+                    Some(w.pid)
+                })
+                .collect()
+        };
+
+        if poll(&mut pfds, None).is_err() {
+            // Most likely EINTR; just rebuild and try again
+            continue;
+        }
+
+        let mut saw_sigchld = false;
+        if pfds[0].revents != 0
+            && let Ok(len) = read.read(&mut buf)
+        {
+            saw_sigchld = buf[..len].contains(&SIGCHLD);
+        }
+
+        let exited: Vec<u32> = pfds[1..]
+            .iter()
+            .zip(pids.iter())
+            .filter(|(pfd, _)| pfd.revents != 0)
+            .map(|(_, pid)| *pid)
+            .collect();
+
+        let mut waiters = reaper.waiters.lock().unwrap();
+        waiters.retain(|w| {
+            let ready = match w.pidfd {
+                Some(_) => exited.contains(&w.pid),
+                None => saw_sigchld,
+            };
+            if ready {
+                w.waker.wake_by_ref();
+            }
+            !ready
+        });
+    }
+}
+
+fn install_sigchld_handler() -> std::io::Result<()> {
+    let installed = HANDLER_INSTALLED.get_or_init(|| unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = sigchld_handler as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_NOCLDSTOP;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        // Record the previous handler before ours can possibly run
+        // so that we can chain to it.
+        if libc::sigaction(libc::SIGCHLD, std::ptr::null(), &mut previous) != 0 {
+            return Err(last_errno());
+        }
+        let _ = PREVIOUS_HANDLER.set(previous);
+        if libc::sigaction(libc::SIGCHLD, &action, std::ptr::null_mut()) != 0 {
+            return Err(last_errno());
+        }
+        Ok(())
+    });
+    installed.map_err(std::io::Error::from_raw_os_error)
+}
+
+fn last_errno() -> i32 {
+    std::io::Error::last_os_error()
+        .raw_os_error()
+        .unwrap_or(libc::EINVAL)
+}
+
+extern "C" fn sigchld_handler(
+    signo: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    let saved_errno = std::io::Error::last_os_error().raw_os_error();
+
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    if fd != -1 {
+        unsafe {
+            libc::write(fd, &SIGCHLD as *const u8 as *const libc::c_void, 1);
+        }
+    }
+
+    // Chain to whatever handler was installed before us so that we
+    // don't break an application that is also managing SIGCHLD.
+    if let Some(previous) = PREVIOUS_HANDLER.get() {
+        let handler = previous.sa_sigaction;
+        if handler != libc::SIG_DFL && handler != libc::SIG_IGN {
+            unsafe {
+                if previous.sa_flags & libc::SA_SIGINFO != 0 {
+                    let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                        std::mem::transmute(handler);
+                    handler(signo, info, context);
+                } else {
+                    let handler: extern "C" fn(libc::c_int) = std::mem::transmute(handler);
+                    handler(signo);
+                }
+            }
+        }
+    }
+
+    if let Some(errno) = saved_errno {
+        set_errno(errno);
+    }
+}
+
+fn set_errno(errno: libc::c_int) {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe {
+        *libc::__errno_location() = errno;
+    }
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    unsafe {
+        *libc::__error() = errno;
+    }
+    #[cfg(any(target_os = "netbsd", target_os = "openbsd"))]
+    unsafe {
+        *libc::__errno() = errno;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Condvar};
+    use std::task::Wake;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Flag {
+        woken: Mutex<bool>,
+        changed: Condvar,
+    }
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            *self.woken.lock().unwrap() = true;
+            self.changed.notify_all();
+        }
+    }
+
+    #[test]
+    fn test_sigchld_fallback() {
+        // Every caller sees the outcome of installing the handler
+        install_sigchld_handler().unwrap();
+        install_sigchld_handler().unwrap();
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("0.1")
+            .spawn()
+            .unwrap();
+        let flag = Arc::new(Flag::default());
+        // Without a pidfd, the waker is woken by SIGCHLD
+        register(child.id(), None, &Waker::from(Arc::clone(&flag))).unwrap();
+
+        let woken = flag.woken.lock().unwrap();
+        let (woken, _) = flag
+            .changed
+            .wait_timeout_while(woken, Duration::from_secs(5), |woken| !*woken)
+            .unwrap();
+        assert!(*woken);
+        drop(woken);
+        assert!(child.wait().unwrap().success());
+    }
+}
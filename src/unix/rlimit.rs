@@ -0,0 +1,135 @@
+//! Resource limits applied to spawned commands
+use anyhow::Context as _;
+#[cfg(feature = "serde_support")]
+use serde::{Deserialize, Serialize};
+
+/// Pass as the soft or hard value of a limit to make it unlimited
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// A resource whose consumption can be limited with
+/// `CommandBuilder::rlimit`.  See `setrlimit(2)` for the details of
+/// each resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub enum Resource {
+    /// The size of the address space, in bytes (RLIMIT_AS)
+    AddressSpace,
+    /// The size of core dumps, in bytes; 0 disables them (RLIMIT_CORE)
+    Core,
+    /// CPU time, in seconds (RLIMIT_CPU)
+    Cpu,
+    /// The size of the data segment, in bytes (RLIMIT_DATA)
+    Data,
+    /// The size of files that can be created, in bytes (RLIMIT_FSIZE)
+    FileSize,
+    /// Memory that can be locked, in bytes (RLIMIT_MEMLOCK)
+    MemLock,
+    /// One more than the largest file descriptor number (RLIMIT_NOFILE)
+    NoFile,
+    /// The number of processes of the user (RLIMIT_NPROC)
+    NProc,
+    /// The size of the stack, in bytes (RLIMIT_STACK)
+    Stack,
+}
+
+impl Resource {
+    pub(crate) fn as_raw(self) -> libc::c_int {
+        (match self {
+            Self::AddressSpace => libc::RLIMIT_AS,
+            Self::Core => libc::RLIMIT_CORE,
+            Self::Cpu => libc::RLIMIT_CPU,
+            Self::Data => libc::RLIMIT_DATA,
+            Self::FileSize => libc::RLIMIT_FSIZE,
+            Self::MemLock => libc::RLIMIT_MEMLOCK,
+            Self::NoFile => libc::RLIMIT_NOFILE,
+            Self::NProc => libc::RLIMIT_NPROC,
+            Self::Stack => libc::RLIMIT_STACK,
+        }) as libc::c_int
+    }
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::AddressSpace => "RLIMIT_AS",
+            Self::Core => "RLIMIT_CORE",
+            Self::Cpu => "RLIMIT_CPU",
+            Self::Data => "RLIMIT_DATA",
+            Self::FileSize => "RLIMIT_FSIZE",
+            Self::MemLock => "RLIMIT_MEMLOCK",
+            Self::NoFile => "RLIMIT_NOFILE",
+            Self::NProc => "RLIMIT_NPROC",
+            Self::Stack => "RLIMIT_STACK",
+        };
+        fmt.write_str(name)
+    }
+}
+
+/// A limit on a resource, as set by `CommandBuilder::rlimit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct ResourceLimit {
+    pub resource: Resource,
+    pub soft: u64,
+    pub hard: u64,
+}
+
+fn to_rlim(value: u64) -> libc::rlim_t {
+    if value == RLIM_INFINITY {
+        libc::RLIM_INFINITY
+    } else {
+        value as libc::rlim_t
+    }
+}
+
+fn describe(value: u64) -> String {
+    if value == RLIM_INFINITY {
+        "unlimited".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+impl ResourceLimit {
+    /// Converts the limit to its raw form, checking that it can be
+    /// applied.  This runs in the parent so that problems are reported
+    /// clearly, rather than as a bare error from the child.
+    pub(crate) fn to_raw(self) -> anyhow::Result<(libc::c_int, libc::rlimit)> {
+        if self.soft > self.hard {
+            anyhow::bail!(
+                "the soft limit {} of {} exceeds its hard limit {}",
+                describe(self.soft),
+                self.resource,
+                describe(self.hard)
+            );
+        }
+
+        let resource = self.resource.as_raw();
+        let mut current = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(resource as _, &mut current) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("getrlimit({})", self.resource));
+        }
+        let raising = current.rlim_max != libc::RLIM_INFINITY
+            && (self.hard == RLIM_INFINITY || to_rlim(self.hard) > current.rlim_max);
+        if raising && unsafe { libc::geteuid() } != 0 {
+            anyhow::bail!(
+                "cannot raise the hard limit of {} from {} to {} without privileges",
+                self.resource,
+                current.rlim_max,
+                describe(self.hard)
+            );
+        }
+
+        Ok((
+            resource,
+            libc::rlimit {
+                rlim_cur: to_rlim(self.soft),
+                rlim_max: to_rlim(self.hard),
+            },
+        ))
+    }
+}
@@ -0,0 +1,60 @@
+//! Decoding pty output as text
+
+/// Decodes UTF-8 text that may have been split across reads or writes.
+/// A sequence that is incomplete at the end of a chunk is held back
+/// until the remainder arrives; invalid bytes become U+FFFD.
+#[derive(Debug, Default)]
+pub(crate) struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    /// Appends `bytes` and returns the text that is now complete
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut text = String::new();
+        let mut rest = &self.pending[..];
+        while !rest.is_empty() {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                }
+                Err(err) => {
+                    let (valid, invalid) = rest.split_at(err.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).expect("prefix is valid"));
+                    match err.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &invalid[len..];
+                        }
+                        // An incomplete sequence at the end; wait for the rest
+                        None => {
+                            rest = invalid;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let consumed = self.pending.len() - rest.len();
+        self.pending.drain(..consumed);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"caf\xc3"), "caf");
+        assert_eq!(decoder.decode(b"\xa9"), "é");
+        // An invalid byte doesn't stop a split sequence after it from
+        // being held back
+        assert_eq!(decoder.decode(b"a\xffb\xe2\x82"), "a\u{fffd}b");
+        assert_eq!(decoder.decode(b"\xac"), "€");
+    }
+}
@@ -5,11 +5,12 @@ use std::os::windows::io::AsRawHandle;
 use std::pin::Pin;
 use std::sync::Mutex;
 use std::task::{Context, Poll};
+use std::time::Instant;
 use winapi::shared::minwindef::DWORD;
 use winapi::um::minwinbase::STILL_ACTIVE;
 use winapi::um::processthreadsapi::*;
 use winapi::um::synchapi::WaitForSingleObject;
-use winapi::um::winbase::INFINITE;
+use winapi::um::winbase::{INFINITE, WAIT_FAILED};
 
 pub mod conpty;
 mod procthreadattr;
@@ -105,6 +106,26 @@ impl Child for WinChild {
         }
     }
 
+    fn wait_deadline(&mut self, deadline: Instant) -> IoResult<Option<ExitStatus>> {
+        let proc = self.proc.lock().unwrap().try_clone().unwrap();
+        loop {
+            if let Some(status) = self.try_wait()? {
+                return Ok(Some(status));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            // Round up so that we don't wake up just short of the deadline
+            let millis = (deadline - now).as_micros().div_ceil(1000);
+            let millis = millis.min((INFINITE - 1) as u128) as DWORD;
+            let res = unsafe { WaitForSingleObject(proc.as_raw_handle() as _, millis) };
+            if res == WAIT_FAILED {
+                return Err(IoError::last_os_error());
+            }
+        }
+    }
+
     fn process_id(&self) -> Option<u32> {
         let res = unsafe { GetProcessId(self.proc.lock().unwrap().as_raw_handle() as _) };
         if res == 0 {
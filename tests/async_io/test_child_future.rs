@@ -0,0 +1,28 @@
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::unix::UnixChild;
+    use portable_pty::{Child, CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
+
+    #[test]
+    #[timeout(5000)]
+    fn test_await_child_exit() {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master: _master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "sleep 0.2; exit 3"]);
+        let mut child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let child: &mut dyn Child = &mut *child;
+        let child = child.downcast_mut::<UnixChild>().unwrap();
+
+        let status = smol::block_on(&mut *child).unwrap();
+        assert_eq!(status.exit_code(), 3);
+
+        // The status is retained once the child has been reaped
+        let status = child.try_wait().unwrap().unwrap();
+        assert_eq!(status.exit_code(), 3);
+    }
+}
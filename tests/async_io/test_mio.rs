@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use crate::common::spawn_sh as spawn;
+    use mio::{Events, Interest, Poll, Token};
+    use ntest::timeout;
+    use portable_pty::MasterPty;
+    use portable_pty::unix::UnixMasterPty;
+    use std::io::{ErrorKind, Read, Write};
+    use std::time::Duration;
+
+    const MASTER: Token = Token(0);
+
+    #[test]
+    #[timeout(5000)]
+    fn test_nonblocking_reader() {
+        let (master, mut child) = spawn("exec sleep 30");
+        let mut reader = master.try_clone_reader().unwrap();
+        let unix_master: &dyn MasterPty = &*master;
+        let unix_master = unix_master.downcast_ref::<UnixMasterPty>().unwrap();
+
+        unix_master.set_nonblocking(true).unwrap();
+        let mut buffer = [0u8; 64];
+        let err = reader.read(&mut buffer).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_mio_event_loop() {
+        let (mut master, mut child) = spawn("read line; echo \"got $line\"");
+        let mut reader = master.try_clone_reader().unwrap();
+        let mut writer = Some(master.take_writer().unwrap());
+
+        let mut poll = Poll::new().unwrap();
+        let unix_master: &mut dyn MasterPty = &mut *master;
+        let unix_master = unix_master.downcast_mut::<UnixMasterPty>().unwrap();
+        poll.registry()
+            .register(unix_master, MASTER, Interest::READABLE | Interest::WRITABLE)
+            .unwrap();
+
+        let mut events = Events::with_capacity(8);
+        let mut output = String::new();
+        let mut eof = false;
+        while !eof {
+            poll.poll(&mut events, Some(Duration::from_secs(2)))
+                .unwrap();
+            assert!(!events.is_empty(), "timed out; output was {:?}", output);
+            for event in &events {
+                assert_eq!(event.token(), MASTER);
+                if event.is_writable()
+                    && let Some(mut writer) = writer.take()
+                {
+                    writer.write_all(b"hello\n").unwrap();
+                }
+                if event.is_readable() || event.is_read_closed() {
+                    let mut buffer = [0u8; 1024];
+                    loop {
+                        match reader.read(&mut buffer) {
+                            Ok(0) => {
+                                eof = true;
+                                break;
+                            }
+                            Ok(n) => output.push_str(&String::from_utf8_lossy(&buffer[..n])),
+                            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                            Err(err) => panic!("{err}"),
+                        }
+                    }
+                }
+            }
+        }
+
+        assert!(output.contains("got hello"), "{:?}", output);
+        let unix_master: &mut dyn MasterPty = &mut *master;
+        let unix_master = unix_master.downcast_mut::<UnixMasterPty>().unwrap();
+        poll.registry().deregister(unix_master).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+}
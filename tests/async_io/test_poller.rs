@@ -0,0 +1,158 @@
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::common::spawn_sh;
+    use ntest::timeout;
+    use portable_pty::unix::{Interest, PollEvent, PtyPoller, Token};
+    use portable_pty::{Child, MasterPty};
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    struct Shell {
+        master: Box<dyn MasterPty + Send>,
+        child: Box<dyn Child + Send + Sync>,
+        reader: Box<dyn Read + Send>,
+        output: String,
+        eof: bool,
+        exited: bool,
+    }
+
+    fn spawn(script: &str) -> Shell {
+        let (master, child) = spawn_sh(script);
+        let reader = master.try_clone_reader().unwrap();
+        Shell {
+            master,
+            child,
+            reader,
+            output: String::new(),
+            eof: false,
+            exited: false,
+        }
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_many_ptys_one_thread() {
+        let poller = PtyPoller::new().unwrap();
+        let mut shells: HashMap<Token, Shell> = (0..8)
+            .map(|i| {
+                let shell = spawn(&format!("read line; echo \"$line {i}\"; exit {i}"));
+                let token = Token(i);
+                poller
+                    .register_master(&*shell.master, token, Interest::BOTH)
+                    .unwrap();
+                poller.register_child(&*shell.child, token).unwrap();
+                (token, shell)
+            })
+            .collect();
+
+        let mut events = vec![];
+        let mut statuses = HashMap::new();
+        while shells.values().any(|shell| !shell.eof || !shell.exited) {
+            poller
+                .poll(&mut events, Some(Duration::from_secs(2)))
+                .unwrap();
+            assert!(!events.is_empty(), "timed out waiting for events");
+            for event in &events {
+                match *event {
+                    PollEvent::Writable(token) => {
+                        let shell = shells.get_mut(&token).unwrap();
+                        shell
+                            .master
+                            .take_writer()
+                            .unwrap()
+                            .write_all(b"hello\n")
+                            .unwrap();
+                        // Only wait for output from now on
+                        poller
+                            .modify_master(&*shell.master, token, Interest::READABLE)
+                            .unwrap();
+                    }
+                    PollEvent::Readable(token) => {
+                        let shell = shells.get_mut(&token).unwrap();
+                        let mut buffer = [0u8; 1024];
+                        let n = shell.reader.read(&mut buffer).unwrap();
+                        shell
+                            .output
+                            .push_str(&String::from_utf8_lossy(&buffer[..n]));
+                        if n == 0 {
+                            shell.eof = true;
+                            poller.deregister_master(&*shell.master).unwrap();
+                        }
+                    }
+                    PollEvent::Exited(token) => {
+                        let shell = shells.get_mut(&token).unwrap();
+                        let status = shell.child.try_wait().unwrap().unwrap();
+                        statuses.insert(token, status.exit_code());
+                        shell.exited = true;
+                        poller.deregister_child(token).unwrap();
+                    }
+                }
+            }
+        }
+
+        for (token, shell) in &shells {
+            assert_eq!(statuses[token], token.0 as u32);
+            assert!(
+                poller.deregister_child(*token).is_err(),
+                "child {} was reported more than once",
+                token.0
+            );
+            assert!(
+                shell.output.contains(&format!("hello {}", token.0)),
+                "{:?}",
+                shell.output
+            );
+        }
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_poll_timeout() {
+        let poller = PtyPoller::new().unwrap();
+        let shell = spawn("exec sleep 30");
+        poller
+            .register_master(&*shell.master, Token(1), Interest::READABLE)
+            .unwrap();
+        poller.register_child(&*shell.child, Token(1)).unwrap();
+        assert!(poller.register_child(&*shell.child, Token(1)).is_err());
+
+        let mut events = vec![];
+        let count = poller
+            .poll(&mut events, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert_eq!(count, 0);
+        assert!(events.is_empty());
+
+        let mut child = shell.child;
+        child.kill().unwrap();
+        poller
+            .poll(&mut events, Some(Duration::from_secs(2)))
+            .unwrap();
+        assert!(
+            events.contains(&PollEvent::Exited(Token(1))),
+            "{:?}",
+            events
+        );
+        child.wait().unwrap();
+        poller.deregister_master(&*shell.master).unwrap();
+        poller.deregister_child(Token(1)).unwrap();
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_register_reaped_child() {
+        // The child's own pidfd is used, rather than one opened for its
+        // pid, which may belong to another process by now
+        let poller = PtyPoller::new().unwrap();
+        let mut shell = spawn("exit 0");
+        shell.child.wait().unwrap();
+        poller.register_child(&*shell.child, Token(3)).unwrap();
+
+        let mut events = vec![];
+        poller
+            .poll(&mut events, Some(Duration::from_secs(2)))
+            .unwrap();
+        assert_eq!(events, vec![PollEvent::Exited(Token(3))]);
+    }
+}
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests {
+    use portable_pty::unix::{UnixChild, UnixMasterPty};
+    use portable_pty::{
+        Child, CommandBuilder, MasterPty, NativePtySystem, PtyPair, PtySize, PtySystem,
+    };
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_async_read_until_eof() {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("echo");
+        cmd.arg("hello");
+        let mut child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let master: &dyn MasterPty = &*master;
+        let master = master.downcast_ref::<UnixMasterPty>().unwrap();
+        let mut reader = master.try_clone_async_reader().unwrap();
+
+        // EIO from the closed slave must be reported as EOF
+        let mut output = String::new();
+        tokio::time::timeout(Duration::from_secs(5), reader.read_to_string(&mut output))
+            .await
+            .expect("timed out waiting for EOF")
+            .unwrap();
+
+        assert!(child.wait().unwrap().success());
+        assert!(output.contains("hello"), "Output was: {:?}", output);
+    }
+
+    #[tokio::test]
+    async fn test_async_write_then_eof() {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut child = slave.spawn_command(CommandBuilder::new("cat")).unwrap();
+        drop(slave);
+
+        let master: &dyn MasterPty = &*master;
+        let master = master.downcast_ref::<UnixMasterPty>().unwrap();
+        let mut reader = master.try_clone_async_reader().unwrap();
+        let mut writer = master.take_async_writer().unwrap();
+        assert!(master.take_async_writer().is_err());
+
+        writer.write_all(b"hello\n").await.unwrap();
+        // Dropping the writer sends EOT, which makes cat exit
+        drop(writer);
+
+        let mut output = String::new();
+        tokio::time::timeout(Duration::from_secs(5), reader.read_to_string(&mut output))
+            .await
+            .expect("timed out waiting for EOF")
+            .unwrap();
+
+        assert!(child.wait().unwrap().success());
+        // Once from the terminal echo and once from cat itself
+        let hello_count = output.matches("hello").count();
+        assert!(hello_count == 2, "Output was: {:?}", output);
+    }
+
+    #[tokio::test]
+    async fn test_wait_async() {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master: _master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "sleep 0.2; exit 5"]);
+        let mut child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let child: &mut dyn Child = &mut *child;
+        let child = child.downcast_mut::<UnixChild>().unwrap();
+
+        let status = tokio::time::timeout(Duration::from_secs(5), child.wait_async())
+            .await
+            .expect("timed out waiting for child to exit")
+            .unwrap();
+        assert_eq!(status.exit_code(), 5);
+    }
+}
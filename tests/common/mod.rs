@@ -0,0 +1,24 @@
+//! Fixtures shared by the integration tests
+use portable_pty::{
+    Child, CommandBuilder, MasterPty, NativePtySystem, PtyPair, PtySize, PtySystem,
+};
+
+/// Opens a native pty, and prepares a command that runs `script`
+/// with `sh` for spawning into it
+pub fn sh_command(script: &str) -> (PtyPair, CommandBuilder) {
+    let pair = NativePtySystem::default()
+        .openpty(PtySize::default())
+        .unwrap();
+    let mut cmd = CommandBuilder::new("sh");
+    cmd.args(["-c", script]);
+    (pair, cmd)
+}
+
+/// Runs `script` with `sh` in a new native pty.  The slave is dropped
+/// so that the master sees EOF once the child is gone.
+pub fn spawn_sh(script: &str) -> (Box<dyn MasterPty + Send>, Box<dyn Child + Send + Sync>) {
+    let (pair, cmd) = sh_command(script);
+    let child = pair.slave.spawn_command(cmd).unwrap();
+    drop(pair.slave);
+    (pair.master, child)
+}
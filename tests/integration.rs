@@ -1,13 +1,60 @@
 // tests/integration.rs
 
+#[cfg(unix)]
+mod common;
+
+#[cfg(unix)]
+mod async_io {
+    mod test_child_future;
+    #[cfg(feature = "mio")]
+    mod test_mio;
+    mod test_poller;
+    #[cfg(feature = "tokio")]
+    mod test_tokio;
+}
+
 mod interactive_session {
     mod slow_reader_thread;
     mod test_bash;
+    #[cfg(feature = "expect")]
+    mod test_expect;
+    mod test_master_reader;
+    mod test_pty_session;
     mod try_reading_pipe_after_child_exit;
 }
 
 mod oneshot_command {
+    mod test_credentials;
     mod test_echo;
+    mod test_exit_status;
+    mod test_inherit_fd;
     mod test_kill;
+    mod test_kill_policy;
+    mod test_kill_scope;
+    mod test_pre_exec;
+    mod test_resource_usage;
+    mod test_rlimit;
+    mod test_run;
+    #[cfg(feature = "serde_support")]
+    mod test_serde;
+    mod test_signal;
+    mod test_spawn_stress;
+    mod test_status_change;
     mod test_wait_before_kill_stress;
+    mod test_wait_timeout;
+}
+
+mod recording {
+    mod test_asciicast;
+    mod test_replay;
+}
+
+#[cfg(feature = "mock")]
+mod mock {
+    mod test_mock;
+}
+
+#[cfg(feature = "screen")]
+mod screen {
+    mod test_screen;
 }
@@ -81,7 +81,7 @@ mod tests {
         // Set up the command to launch Bash with no profile, no rc, and empty prompt.
         let mut cmd = CommandBuilder::new(SHELL_COMMAND);
         for arg in SHELL_ARGS {
-            let _ = cmd.arg(arg);
+            cmd.arg(arg);
         }
 
         cmd.env("PROMPT", PROMPT_SIGN);
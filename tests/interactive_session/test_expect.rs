@@ -0,0 +1,82 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::common::sh_command;
+    use ntest::timeout;
+    use portable_pty::expect::{Pattern, Session, TranscriptEntry};
+    use std::time::Duration;
+
+    fn spawn(script: &str) -> Session {
+        let (pair, cmd) = sh_command(script);
+        Session::spawn(pair, cmd).unwrap()
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_expect_conversation() {
+        let mut session = spawn("echo 'name?'; read name; echo \"hello $name, bye\"; exit 3");
+
+        let found = session.expect("name?").unwrap();
+        assert_eq!(found.after, "name?");
+
+        session.send_line("world").unwrap();
+        let found = session
+            .expect(Pattern::regex(r"hello (\w+),").unwrap())
+            .unwrap();
+        // The terminal echoes our input before the reply
+        assert!(found.before.contains("world"), "before: {:?}", found.before);
+        assert_eq!(found.captures[1].as_deref(), Some("world"));
+
+        let found = session.expect(Pattern::Eof).unwrap();
+        assert!(found.before.contains("bye"), "before: {:?}", found.before);
+        assert_eq!(session.wait().unwrap().exit_code(), 3);
+
+        assert!(
+            session
+                .transcript()
+                .contains(&TranscriptEntry::Sent("world\n".to_string()))
+        );
+        assert!(
+            session
+                .transcript()
+                .iter()
+                .any(|entry| matches!(entry, TranscriptEntry::Received(_)))
+        );
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_expect_alternatives() {
+        let mut session = spawn("echo first second; sleep 10");
+
+        // The earliest match in the output wins, regardless of order
+        let found = session
+            .expect_any(&["second".into(), "first".into()])
+            .unwrap();
+        assert_eq!(found.index, 1);
+
+        session.set_timeout(Duration::from_millis(100));
+        let found = session
+            .expect_any(&["never".into(), Pattern::Timeout])
+            .unwrap();
+        assert_eq!(found.index, 1);
+        assert!(
+            found.before.contains("second"),
+            "before: {:?}",
+            found.before
+        );
+
+        // Without Pattern::Timeout, timing out is an error
+        let err = session.expect("never").unwrap_err();
+        assert!(err.to_string().contains("timed out"), "error: {:#}", err);
+
+        session.child().kill().unwrap();
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_unexpected_eof() {
+        let mut session = spawn("echo done");
+        let err = session.expect("never").unwrap_err();
+        assert!(err.to_string().contains("EOF"), "error: {:#}", err);
+    }
+}
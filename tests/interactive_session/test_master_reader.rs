@@ -0,0 +1,73 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::common::spawn_sh as spawn;
+    use ntest::timeout;
+    use portable_pty::MasterPty;
+    use portable_pty::unix::{UnixMasterPty, UnixMasterReader};
+    use std::io::{ErrorKind, Read};
+    use std::time::{Duration, Instant};
+
+    fn master_reader(master: &dyn MasterPty) -> UnixMasterReader {
+        master
+            .downcast_ref::<UnixMasterPty>()
+            .unwrap()
+            .try_clone_master_reader()
+            .unwrap()
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_read_until_eof() {
+        let (master, mut child) = spawn("echo hello");
+        let mut reader = master_reader(&*master);
+        reader.set_read_timeout(Some(Duration::from_secs(2)));
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert!(output.contains("hello"), "{:?}", output);
+        child.wait().unwrap();
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_timeout_and_nonblocking() {
+        let (master, mut child) = spawn("exec sleep 30");
+        let mut reader = master_reader(&*master);
+        let mut buffer = [0u8; 64];
+
+        reader.set_read_timeout(Some(Duration::from_millis(100)));
+        let start = Instant::now();
+        let err = reader.read(&mut buffer).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        reader.set_nonblocking(true);
+        let err = reader.read(&mut buffer).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_interrupt() {
+        let (master, mut child) = spawn("exec sleep 30");
+        let mut reader = master_reader(&*master);
+        let handle = reader.interrupt_handle();
+
+        let thread = std::thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+            let n = reader.read(&mut buffer).unwrap();
+            // Further reads see EOF too
+            (n, reader.read(&mut buffer).unwrap())
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_interrupted());
+        handle.interrupt();
+        assert_eq!(thread.join().unwrap(), (0, 0));
+        assert!(handle.is_interrupted());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
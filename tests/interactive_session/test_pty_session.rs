@@ -0,0 +1,71 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::common::sh_command;
+    use ntest::timeout;
+    use portable_pty::session::PtySession;
+    use std::io::Write;
+    use std::time::Duration;
+
+    fn spawn(script: &str) -> PtySession {
+        let (pair, cmd) = sh_command(script);
+        PtySession::spawn(pair, cmd).unwrap()
+    }
+
+    /// Receives output until it contains `needle`
+    fn read_until(session: &PtySession, needle: &str) -> String {
+        let mut output = String::new();
+        while !output.contains(needle) {
+            let chunk = session
+                .output()
+                .recv_timeout(Duration::from_secs(2))
+                .unwrap_or_else(|err| panic!("{err}; output was {output:?}"));
+            output.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        output
+    }
+
+    fn is_running(pid: u32) -> bool {
+        unsafe { libc::kill(pid as i32, 0) == 0 }
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_close_kills_running_child() {
+        let mut session = spawn("while read line; do echo \"got $line\"; done");
+        session.write_all(b"one\n").unwrap();
+        read_until(&session, "got one");
+
+        let closed = session.close().unwrap();
+        assert!(!closed.status.success());
+        assert!(closed.drained);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_close_after_exit_keeps_output() {
+        let mut session = spawn("echo finished; exit 5");
+        session.child_mut().wait().unwrap();
+
+        let closed = session.close().unwrap();
+        assert_eq!(closed.status.exit_code(), 5);
+        let output = String::from_utf8_lossy(&closed.output);
+        assert!(output.contains("finished"), "{:?}", output);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_drop_and_detach() {
+        let session = spawn("exec sleep 30");
+        let pid = session.child().process_id().unwrap();
+        drop(session);
+        // Dropping reaps the child, so the pid is gone
+        assert!(!is_running(pid));
+
+        let session = spawn("exec sleep 30");
+        let pid = session.child().process_id().unwrap();
+        let mut parts = session.detach();
+        assert!(is_running(pid));
+        parts.child.kill().unwrap();
+        parts.child.wait().unwrap();
+    }
+}
@@ -60,7 +60,7 @@ mod tests {
         // Set up the command to launch Bash with no profile, no rc, and empty prompt.
         let mut cmd = CommandBuilder::new(SHELL_COMMAND);
         for arg in SHELL_ARGS {
-            let _ = cmd.arg(arg);
+            cmd.arg(arg);
         }
 
         cmd.env("PROMPT", PROMPT_SIGN);
@@ -169,7 +169,7 @@ mod tests {
         let (tx_waiter, rx_waiter) = channel();
         let waiter_handle = thread::spawn(move || {
             let status = child.lock().unwrap().wait().unwrap();
-            let _ = tx_waiter.send(status).unwrap();
+            tx_waiter.send(status).unwrap();
         });
 
         // Wait for shell to exit
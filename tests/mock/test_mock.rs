@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::mock::MockPtySystem;
+    use portable_pty::{CommandBuilder, ExitStatus, PtySize, PtySystem};
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    #[test]
+    #[timeout(5000)]
+    fn test_output_and_input() {
+        let pty_system = MockPtySystem::new();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+        let pty = pty_system.last_pty().unwrap();
+
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let feeder = std::thread::spawn({
+            let pty = pty.clone();
+            move || {
+                pty.write_output(b"hello ");
+                pty.write_output(b"world");
+                pty.close_output();
+            }
+        });
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        feeder.join().unwrap();
+        assert_eq!(output, "hello world");
+
+        let mut writer = pair.master.take_writer().unwrap();
+        assert!(pair.master.take_writer().is_err());
+        writer.write_all(b"ls\r").unwrap();
+        assert_eq!(pty.take_input(), b"ls\r");
+        writer.write_all(b"exit\r").unwrap();
+        assert_eq!(pty.input(), b"exit\r");
+
+        let size = PtySize {
+            rows: 50,
+            cols: 132,
+            ..PtySize::default()
+        };
+        pair.master.resize(size).unwrap();
+        assert_eq!(pair.master.get_size().unwrap(), size);
+        assert_eq!(pty.resizes(), vec![size]);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_child_exit() {
+        let pty_system = MockPtySystem::new();
+        let pair = pty_system.clone().openpty(PtySize::default()).unwrap();
+        let mut child = pair
+            .slave
+            .spawn_command(CommandBuilder::new("true"))
+            .unwrap();
+        let pty = &pty_system.ptys()[0];
+        assert_eq!(pty.commands(), vec![CommandBuilder::new("true")]);
+
+        assert!(child.try_wait().unwrap().is_none());
+        assert!(
+            child
+                .wait_timeout(Duration::from_millis(10))
+                .unwrap()
+                .is_none()
+        );
+
+        let waiter = std::thread::spawn(move || child.wait().unwrap());
+        pty.exit(ExitStatus::with_exit_code(7));
+        assert_eq!(waiter.join().unwrap().exit_code(), 7);
+        assert_eq!(pty.exit_status().unwrap().exit_code(), 7);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_child_kill() {
+        let pty_system = MockPtySystem::new();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+        let mut child = pair
+            .slave
+            .spawn_command(CommandBuilder::new("sleep"))
+            .unwrap();
+        let pty = pty_system.last_pty().unwrap();
+
+        #[cfg(unix)]
+        {
+            use portable_pty::unix::Signal;
+            child.signal(Signal::SIGINT).unwrap();
+            assert_eq!(pty.signals(), vec![Signal::SIGINT]);
+            assert!(child.try_wait().unwrap().is_none());
+        }
+
+        child.clone_killer().kill().unwrap();
+        let status = child.wait().unwrap();
+        assert!(!status.success());
+        assert_eq!(status.signal(), Some("Killed"));
+        assert_eq!(pty.kill_count(), 1);
+
+        // Exiting after the fact doesn't change the status
+        pty.exit(ExitStatus::with_exit_code(0));
+        assert!(!child.wait().unwrap().success());
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_kill_closes_output() {
+        let pty_system = MockPtySystem::new();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+        let mut child = pair
+            .slave
+            .spawn_command(CommandBuilder::new("sleep"))
+            .unwrap();
+        let pty = pty_system.last_pty().unwrap();
+        pty.write_output(b"partial");
+
+        // A reader blocked on the output sees EOF once the child is killed
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let collector = std::thread::spawn(move || {
+            let mut output = String::new();
+            reader.read_to_string(&mut output).unwrap();
+            output
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        child.kill().unwrap();
+        assert_eq!(collector.join().unwrap(), "partial");
+        assert_eq!(child.wait().unwrap().signal(), Some("Killed"));
+    }
+}
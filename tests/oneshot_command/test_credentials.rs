@@ -0,0 +1,95 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::CommandBuilder;
+    use portable_pty::run::{RunOptions, run};
+
+    /// Changing user needs root; returns the uid and gid of "nobody"
+    /// if the test can run
+    fn nobody() -> Option<(libc::uid_t, libc::gid_t)> {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipping: changing user requires root");
+            return None;
+        }
+        let ent = unsafe { libc::getpwnam(c"nobody".as_ptr()) };
+        if ent.is_null() {
+            eprintln!("skipping: there is no nobody user");
+            return None;
+        }
+        Some(unsafe { ((*ent).pw_uid, (*ent).pw_gid) })
+    }
+
+    fn run_script(cmd_setup: impl FnOnce(&mut CommandBuilder)) -> Vec<String> {
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args([
+            "-c",
+            "id -u; id -g; id -G; echo \"$HOME\"; echo \"$USER\"; echo \"$LOGNAME\"; pwd; echo \"$SHELL\"",
+        ]);
+        cmd_setup(&mut cmd);
+        let result = run(cmd, &RunOptions::new()).unwrap();
+        let output = String::from_utf8_lossy(&result.output).to_string();
+        assert!(result.status.success(), "{:?}", output);
+        output.lines().map(|line| line.trim().to_string()).collect()
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_uid_resolves_user() {
+        let Some((uid, gid)) = nobody() else {
+            return;
+        };
+        let lines = run_script(|cmd| cmd.uid(Some(uid)));
+        assert_eq!(lines[0], uid.to_string());
+        // The primary group of the user is used, and our own
+        // supplementary groups are dropped
+        assert_eq!(lines[1], gid.to_string());
+        assert_eq!(lines[2], gid.to_string());
+        let entry = unsafe { &*libc::getpwuid(uid) };
+        let home = unsafe { std::ffi::CStr::from_ptr(entry.pw_dir) };
+        assert_eq!(lines[3], home.to_str().unwrap());
+        assert_eq!(lines[4], "nobody");
+        assert_eq!(lines[5], "nobody");
+        if !std::path::Path::new(&lines[3]).is_dir() {
+            assert_eq!(lines[6], "/");
+        }
+        // SHELL is the user's too, or /bin/sh if theirs isn't usable
+        let shell = unsafe { std::ffi::CStr::from_ptr(entry.pw_shell) };
+        if unsafe { libc::access(shell.as_ptr(), libc::X_OK) } == 0 {
+            assert_eq!(lines[7], shell.to_str().unwrap());
+        } else {
+            assert_eq!(lines[7], "/bin/sh");
+        }
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_explicit_groups_and_env() {
+        let Some((uid, _)) = nobody() else {
+            return;
+        };
+        let lines = run_script(|cmd| {
+            cmd.uid(Some(uid));
+            cmd.gid(Some(4242));
+            cmd.groups(Some(vec![4243, 4244]));
+            cmd.env("HOME", "/tmp");
+            cmd.env("SHELL", "/custom/shell");
+        });
+        assert_eq!(lines[0], uid.to_string());
+        assert_eq!(lines[1], "4242");
+        let mut groups: Vec<&str> = lines[2].split_whitespace().collect();
+        groups.sort();
+        assert_eq!(groups, ["4242", "4243", "4244"]);
+        assert_eq!(lines[3], "/tmp");
+        assert_eq!(lines[6], "/tmp");
+        assert_eq!(lines[7], "/custom/shell");
+    }
+
+    #[test]
+    fn test_unknown_uid_needs_gid() {
+        let mut cmd = CommandBuilder::new("true");
+        // Unlikely to be in the password database
+        cmd.uid(Some(3_999_999_999));
+        let err = run(cmd, &RunOptions::new()).unwrap_err();
+        assert!(format!("{err:#}").contains("set a gid"), "{err:#}");
+    }
+}
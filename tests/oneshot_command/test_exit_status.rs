@@ -0,0 +1,48 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::{CommandBuilder, ExitStatus, NativePtySystem, PtySize, PtySystem};
+
+    fn run(script: &str) -> ExitStatus {
+        let pty_system = NativePtySystem::default();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", script]);
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+
+        child.wait().unwrap()
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_exit_code() {
+        let status = run("exit 1");
+        assert!(!status.success());
+        assert_eq!(status.exit_code(), 1);
+        assert_eq!(status.code(), Some(1));
+        assert_eq!(status.signal(), None);
+        assert_eq!(status.signal_number(), None);
+        assert!(!status.core_dumped());
+        assert_eq!(status.raw_status(), Some(1 << 8));
+        assert_eq!(status.to_string(), "Exited with code 1");
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_killed_by_signal() {
+        let status = run("ulimit -c 0; kill -SEGV $$");
+        assert!(!status.success());
+        // The exit code is still reported as 1 for compatibility
+        assert_eq!(status.exit_code(), 1);
+        assert_eq!(status.code(), None);
+        assert_eq!(status.signal_number(), Some(libc::SIGSEGV));
+        assert!(!status.core_dumped());
+        assert_eq!(status.raw_status(), Some(libc::SIGSEGV));
+        assert_eq!(
+            status.to_string(),
+            format!("Terminated by {}", status.signal().unwrap())
+        );
+    }
+}
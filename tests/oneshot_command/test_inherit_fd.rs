@@ -0,0 +1,107 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use filedescriptor::Pipe;
+    use ntest::timeout;
+    use portable_pty::CommandBuilder;
+    use portable_pty::run::{RunOptions, run};
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+
+    /// Closes our write end and reads what the child wrote
+    fn read_all(pipe: Pipe) -> String {
+        let Pipe { mut read, write } = pipe;
+        drop(write);
+        let mut text = String::new();
+        read.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_inherit_fd() {
+        let pipe = Pipe::new().unwrap();
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "echo passed >&7"]);
+        cmd.inherit_fd(pipe.write.as_raw_fd(), 7);
+        let result = run(cmd, &RunOptions::new()).unwrap();
+        assert!(result.status.success());
+
+        assert_eq!(read_all(pipe), "passed\n");
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_inherit_fd_swap() {
+        // Each fd is moved to the number of the other one, so
+        // moving the first into place must not clobber the second
+        let first = Pipe::new().unwrap();
+        let second = Pipe::new().unwrap();
+        let first_fd = first.write.as_raw_fd();
+        let second_fd = second.write.as_raw_fd();
+
+        // Unlike some other shells, bash can redirect to fds above 9
+        let mut cmd = CommandBuilder::new("bash");
+        cmd.args([
+            "-c",
+            &format!("echo to-first >&{second_fd}; echo to-second >&{first_fd}"),
+        ]);
+        cmd.inherit_fd(first_fd, second_fd);
+        cmd.inherit_fd(second_fd, first_fd);
+        let result = run(cmd, &RunOptions::new()).unwrap();
+        assert!(result.status.success());
+
+        assert_eq!(read_all(first), "to-first\n");
+        assert_eq!(read_all(second), "to-second\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[timeout(5000)]
+    fn test_inherit_stdio_fd() {
+        // The child's stdio is the pty by the time inherited fds are
+        // moved into place; our own stderr must be passed on instead
+        let stderr = std::fs::read_link("/proc/self/fd/2").unwrap();
+        let mut cmd = CommandBuilder::new("readlink");
+        cmd.args(["/proc/self/fd/7"]);
+        cmd.inherit_fd(2, 7);
+        let result = run(cmd, &RunOptions::new()).unwrap();
+        assert!(result.status.success());
+
+        let output = String::from_utf8_lossy(&result.output);
+        assert_eq!(output.trim(), stderr.to_str().unwrap());
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_inherit_fd_keeps_spawn_errors() {
+        // std reports spawn errors through a pipe that takes the lowest
+        // free fd; inheriting onto the free fds must not clobber it
+        let pipe = Pipe::new().unwrap();
+        let mut cmd = CommandBuilder::new("true");
+        let free_fds = (3..)
+            .filter(|&fd| unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1)
+            .take(16);
+        for fd in free_fds {
+            cmd.inherit_fd(pipe.write.as_raw_fd(), fd);
+        }
+        unsafe {
+            cmd.pre_exec(|| Err(std::io::Error::from_raw_os_error(libc::EPERM)));
+        }
+        let err = run(cmd, &RunOptions::new()).unwrap_err();
+        let err = err.downcast::<std::io::Error>().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+
+        assert_eq!(read_all(pipe), "");
+    }
+
+    #[test]
+    fn test_inherit_closed_fd() {
+        let mut cmd = CommandBuilder::new("true");
+        cmd.inherit_fd(9999, 3);
+        let err = run(cmd, &RunOptions::new()).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("cannot inherit fd 9999"),
+            "{err:#}"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::unix::{KillPolicy, Signal};
+    use portable_pty::{Child, CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
+    use std::io::Read;
+    use std::time::Duration;
+
+    /// Spawns a shell that ignores SIGHUP and SIGTERM, and waits until
+    /// it has installed its traps.
+    fn spawn_stubborn_child(
+        kill_policy: Option<KillPolicy>,
+    ) -> (Box<dyn Child + Send + Sync>, Box<dyn Read + Send>) {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args([
+            "-c",
+            "trap '' HUP TERM; echo ready; while :; do sleep 0.05; done",
+        ]);
+        if let Some(policy) = kill_policy {
+            cmd.set_kill_policy(policy);
+        }
+        let child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let mut reader = master.try_clone_reader().unwrap();
+        let mut output = String::new();
+        let mut buffer = [0u8; 1024];
+        while !output.contains("ready") {
+            let n = reader.read(&mut buffer).unwrap();
+            assert!(n > 0, "Unexpected EOF, output was: {:?}", output);
+            output.push_str(&String::from_utf8_lossy(&buffer[..n]));
+        }
+
+        (child, reader)
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_default_policy_escalates_from_cloned_killer() {
+        let (mut child, _reader) = spawn_stubborn_child(None);
+
+        // SIGHUP is ignored, so this only succeeds if the cloned killer
+        // escalates to SIGKILL like the child itself does
+        let mut killer = child.clone_killer();
+        let waiter = std::thread::spawn(move || child.wait());
+        killer.kill().unwrap();
+
+        let status = waiter.join().unwrap().unwrap();
+        assert_eq!(status.signal(), Some("Killed"), "status: {}", status);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_builder_policy_is_used_by_kill() {
+        let mut policy = KillPolicy::new();
+        policy.add_step(Signal::SIGTERM, Duration::from_millis(100));
+        policy.add_step(Signal::SIGINT, Duration::from_millis(100));
+        policy.add_step(Signal::SIGKILL, Duration::ZERO);
+        let (mut child, _reader) = spawn_stubborn_child(Some(policy));
+
+        child.kill().unwrap();
+        let status = child.wait().unwrap();
+        // SIGINT is not trapped, so the policy stops short of SIGKILL
+        assert_eq!(status.signal(), Some("Interrupt"), "status: {}", status);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_kill_with_policy() {
+        let (mut child, _reader) = spawn_stubborn_child(None);
+
+        // A policy whose steps are all ignored leaves the child running
+        let mut policy = KillPolicy::new();
+        policy.add_step(Signal::SIGTERM, Duration::from_millis(50));
+        child.kill_with_policy(&policy).unwrap();
+        assert!(child.try_wait().unwrap().is_none());
+
+        let mut policy = KillPolicy::new();
+        policy.add_step(Signal::SIGKILL, Duration::from_secs(1));
+        child.clone_killer().kill_with_policy(&policy).unwrap();
+        let status = child.wait().unwrap();
+        assert_eq!(status.signal(), Some("Killed"), "status: {}", status);
+    }
+}
@@ -0,0 +1,148 @@
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::unix::{KillPolicy, KillScope, Signal};
+    use portable_pty::{Child, CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+
+    /// Spawns a shell that starts a background `sleep` which ignores
+    /// SIGHUP, so that it survives the shell going away, and returns
+    /// the pid of the `sleep`.
+    /// With `job_control` the `sleep` is placed in its own process group.
+    fn spawn_with_background_job(
+        job_control: bool,
+    ) -> (Box<dyn Child + Send + Sync>, Box<dyn Read + Send>, i32) {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sh");
+        // The job reports its pid only once it is ignoring SIGHUP
+        let script = "sh -c 'trap \"\" HUP; echo bg=$$; exec sleep 30' & wait";
+        let script = if job_control {
+            format!("set -m; {script}")
+        } else {
+            script.to_string()
+        };
+        cmd.args(["-c", &script]);
+        let child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let mut reader = master.try_clone_reader().unwrap();
+        let mut output = String::new();
+        let mut buffer = [0u8; 1024];
+        let pid = loop {
+            if let Some(pid) = output
+                .split_once("bg=")
+                .and_then(|(_, rest)| rest.split_once('\n'))
+                .map(|(pid, _)| pid.trim().parse::<i32>().unwrap())
+            {
+                break pid;
+            }
+            let n = reader.read(&mut buffer).unwrap();
+            assert!(n > 0, "Unexpected EOF, output was: {:?}", output);
+            output.push_str(&String::from_utf8_lossy(&buffer[..n]));
+        };
+
+        (child, reader, pid)
+    }
+
+    /// Returns true if `pid` is running; zombies that are waiting to
+    /// be reaped by init don't count.
+    fn is_running(pid: i32) -> bool {
+        match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+            Ok(stat) => !stat.rsplit_once(')').unwrap().1.trim_start().starts_with('Z'),
+            Err(_) => false,
+        }
+    }
+
+    fn wait_until_gone(pid: i32) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if !is_running(pid) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    fn policy(scope: KillScope) -> KillPolicy {
+        let mut policy = KillPolicy::new();
+        policy.add_step(Signal::SIGTERM, Duration::from_millis(500));
+        policy.add_step(Signal::SIGKILL, Duration::ZERO);
+        policy.set_scope(scope);
+        policy
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_process_scope_leaves_background_job() {
+        let (mut child, _reader, bg) = spawn_with_background_job(false);
+
+        child.kill_with_policy(&policy(KillScope::Process)).unwrap();
+        child.wait().unwrap();
+        assert!(is_running(bg));
+
+        unsafe { libc::kill(bg, libc::SIGKILL) };
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_process_group_scope() {
+        let (mut child, _reader, bg) = spawn_with_background_job(false);
+
+        child
+            .clone_killer()
+            .kill_with_policy(&policy(KillScope::ProcessGroup))
+            .unwrap();
+        child.wait().unwrap();
+        assert!(wait_until_gone(bg));
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_session_scope() {
+        let (mut child, _reader, bg) = spawn_with_background_job(true);
+
+        // The job is in a different process group, so signalling the
+        // group of the child doesn't reach it
+        child
+            .signal_scoped(Signal::SIGTERM, KillScope::ProcessGroup)
+            .unwrap();
+        child.wait().unwrap();
+        assert!(is_running(bg));
+
+        // ...but it's still in the session led by the child
+        let mut killer = child.clone_killer();
+        killer
+            .signal_scoped(Signal::SIGTERM, KillScope::Session)
+            .unwrap();
+        assert!(wait_until_gone(bg));
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_scopes_after_reaped() {
+        let pty_system = NativePtySystem::default();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+        let mut child = pair
+            .slave
+            .spawn_command(CommandBuilder::new("true"))
+            .unwrap();
+        drop(pair.slave);
+        let mut killer = child.clone_killer();
+        child.wait().unwrap();
+
+        // Nothing is left to signal, and the id of the child may now
+        // belong to an unrelated process, so every scope is refused
+        for scope in [
+            KillScope::Process,
+            KillScope::ProcessGroup,
+            KillScope::Session,
+        ] {
+            let err = killer.signal_scoped(Signal::SIGTERM, scope).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::ESRCH), "{scope:?}");
+        }
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::CommandBuilder;
+    use portable_pty::run::{RunOptions, run};
+
+    #[test]
+    #[timeout(5000)]
+    fn test_pre_exec_runs_after_setup() {
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "umask"]);
+        cmd.umask(Some(0o022));
+        // Runs after the umask configured above, so this one wins
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::umask(0o027);
+                Ok(())
+            });
+            cmd.pre_exec(|| {
+                libc::umask(libc::umask(0) | 0o050);
+                Ok(())
+            });
+        }
+        let result = run(cmd, &RunOptions::new()).unwrap();
+        assert!(result.status.success());
+        assert_eq!(String::from_utf8_lossy(&result.output).trim(), "0077");
+    }
+
+    #[test]
+    fn test_pre_exec_error() {
+        let mut cmd = CommandBuilder::new("true");
+        unsafe {
+            cmd.pre_exec(|| Err(std::io::Error::from_raw_os_error(libc::EPERM)));
+        }
+        let err = run(cmd, &RunOptions::new()).unwrap_err();
+        let err = err.downcast::<std::io::Error>().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+    }
+
+    #[test]
+    fn test_pre_exec_clone() {
+        let mut cmd = CommandBuilder::new("true");
+        unsafe {
+            cmd.pre_exec(|| Ok(()));
+        }
+        // Clones share the hooks
+        assert_eq!(cmd.clone(), cmd);
+
+        let mut other = cmd.clone();
+        unsafe {
+            other.pre_exec(|| Ok(()));
+        }
+        assert_ne!(other, cmd);
+    }
+}
@@ -0,0 +1,32 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::unix::UnixChild;
+    use portable_pty::{Child, CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
+    use std::time::Duration;
+
+    #[test]
+    #[timeout(5000)]
+    fn test_wait_with_resource_usage() {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master: _master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        // Burn some CPU so that there is something to measure
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "i=0; while [ $i -lt 20000 ]; do i=$((i+1)); done"]);
+        let mut child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let child: &mut dyn Child = &mut *child;
+        let child = child.downcast_mut::<UnixChild>().unwrap();
+        assert!(child.resource_usage().is_none());
+
+        let (status, usage) = child.wait_with_resource_usage().unwrap();
+        assert!(status.success(), "status: {}", status);
+        assert!(usage.user_time + usage.system_time > Duration::ZERO);
+        assert!(usage.max_rss > 0);
+
+        // The usage is retained once the child has been reaped
+        assert_eq!(child.resource_usage(), Some(&usage));
+    }
+}
@@ -0,0 +1,44 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::CommandBuilder;
+    use portable_pty::run::{RunOptions, run};
+    use portable_pty::unix::{RLIM_INFINITY, Resource};
+
+    fn limits(setup: impl FnOnce(&mut CommandBuilder)) -> anyhow::Result<Vec<String>> {
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "ulimit -n; ulimit -Hn; ulimit -c; ulimit -f"]);
+        setup(&mut cmd);
+        let result = run(cmd, &RunOptions::new())?;
+        assert!(result.status.success());
+        Ok(String::from_utf8_lossy(&result.output)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect())
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_rlimit() {
+        let lines = limits(|cmd| {
+            cmd.rlimit(Resource::NoFile, 100, 200);
+            cmd.rlimit(Resource::Core, 0, 0);
+            cmd.rlimit(Resource::FileSize, 1024, RLIM_INFINITY);
+            // Replaces the previous limit
+            cmd.rlimit(Resource::NoFile, 64, 128);
+        })
+        .unwrap();
+        // The shell reports the file size in blocks of 512 or 1024 bytes
+        assert_eq!(lines[..3], ["64", "128", "0"]);
+        assert!(lines[3] == "2" || lines[3] == "1", "{:?}", lines);
+    }
+
+    #[test]
+    fn test_invalid_rlimit() {
+        let err = limits(|cmd| cmd.rlimit(Resource::NoFile, 200, 100)).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("exceeds its hard limit"),
+            "{err:#}"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::replay::{Pacing, Recording, ReplayPtySystem};
+    use portable_pty::run::{RunOptions, run, run_with};
+    use portable_pty::{CommandBuilder, ExitStatus};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[timeout(5000)]
+    fn test_run_echo() {
+        let mut cmd = CommandBuilder::new("echo");
+        cmd.arg("hello");
+        let result = run(cmd, &RunOptions::new()).unwrap();
+        assert!(result.status.success());
+        assert!(!result.timed_out);
+        let output = String::from_utf8_lossy(&result.output);
+        assert_eq!(output.matches("hello").count(), 1, "{:?}", output);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[timeout(5000)]
+    fn test_run_stdin_and_exit_status() {
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "read line; echo \"got $line\"; exit 3"]);
+        let mut options = RunOptions::new();
+        options.set_stdin(Some(b"value\n".to_vec()));
+        let result = run(cmd, &options).unwrap();
+        assert_eq!(result.status.exit_code(), 3);
+        let output = String::from_utf8_lossy(&result.output);
+        assert!(output.contains("got value"), "{:?}", output);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[timeout(5000)]
+    fn test_run_timeout() {
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "echo started; exec sleep 30"]);
+        let mut options = RunOptions::new();
+        options.set_timeout(Some(Duration::from_millis(500)));
+
+        let start = Instant::now();
+        let result = run(cmd, &options).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert!(result.timed_out);
+        assert!(!result.status.success());
+        let output = String::from_utf8_lossy(&result.output);
+        assert!(output.contains("started"), "{:?}", output);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_run_with_pty_system() {
+        let mut recording =
+            Recording::from_script("0.0 8\n".as_bytes(), "replayed".as_bytes()).unwrap();
+        recording.set_exit_status(ExitStatus::with_exit_code(4));
+        let pty_system = ReplayPtySystem::new(recording, Pacing::Immediate);
+        let result = run_with(
+            &pty_system,
+            CommandBuilder::new_default_prog(),
+            &RunOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(result.output, b"replayed");
+        assert_eq!(result.status.exit_code(), 4);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    #[timeout(5000)]
+    fn test_run_exit_just_before_timeout() {
+        use portable_pty::mock::MockPtySystem;
+
+        // The child exits in time, but its output arrives after the
+        // timeout; it is still collected in full
+        let pty_system = MockPtySystem::new();
+        let feeder = std::thread::spawn({
+            let pty_system = pty_system.clone();
+            move || {
+                let pty = loop {
+                    match pty_system.last_pty() {
+                        Some(pty) => break pty,
+                        None => std::thread::sleep(Duration::from_millis(1)),
+                    }
+                };
+                std::thread::sleep(Duration::from_millis(250));
+                pty.exit(ExitStatus::with_exit_code(0));
+                std::thread::sleep(Duration::from_millis(200));
+                pty.write_output(b"finished");
+                pty.close_output();
+            }
+        });
+        let mut options = RunOptions::new();
+        options.set_timeout(Some(Duration::from_millis(300)));
+        let result = run_with(&pty_system, CommandBuilder::new("true"), &options).unwrap();
+        feeder.join().unwrap();
+        assert!(result.status.success());
+        assert!(!result.timed_out);
+        assert_eq!(result.output, b"finished");
+    }
+}
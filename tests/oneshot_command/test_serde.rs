@@ -0,0 +1,99 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use portable_pty::unix::{KillPolicy, KillScope, Resource, Signal};
+    use portable_pty::{CommandBuilder, ExitStatus};
+    use std::time::Duration;
+
+    /// A builder that JSON can represent; the environment is keyed by
+    /// `OsString`, which doesn't serialize as a JSON object key
+    fn command(program: &str) -> CommandBuilder {
+        let mut cmd = CommandBuilder::new(program);
+        cmd.env_clear();
+        cmd
+    }
+
+    fn round_trip(cmd: &CommandBuilder) -> CommandBuilder {
+        serde_json::from_str(&serde_json::to_string(cmd).unwrap()).unwrap()
+    }
+
+    /// Serializes `cmd` without `keys`, as an older version of the
+    /// crate that didn't know about them would have
+    fn without_keys(cmd: &CommandBuilder, keys: &[&str]) -> CommandBuilder {
+        let mut value = serde_json::to_value(cmd).unwrap();
+        for key in keys {
+            value.as_object_mut().unwrap().remove(*key).unwrap();
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_kill_policy() {
+        let mut policy = KillPolicy::new();
+        policy.add_step(Signal::SIGTERM, Duration::from_millis(250));
+        policy.set_scope(KillScope::ProcessGroup);
+        let mut cmd = command("sleep");
+        cmd.set_kill_policy(policy);
+        assert_eq!(round_trip(&cmd), cmd);
+
+        let old = without_keys(&cmd, &["kill_policy"]);
+        assert_eq!(old.get_kill_policy(), &KillPolicy::default());
+        assert_eq!(old.get_argv(), cmd.get_argv());
+    }
+
+    #[test]
+    fn test_rlimits() {
+        let mut cmd = command("sleep");
+        cmd.rlimit(Resource::NoFile, 256, 1024);
+        cmd.rlimit(Resource::Core, 0, 0);
+        assert_eq!(round_trip(&cmd), cmd);
+
+        let old = without_keys(&cmd, &["rlimits"]);
+        assert!(old.get_rlimits().is_empty());
+        assert_eq!(old.get_argv(), cmd.get_argv());
+    }
+
+    #[test]
+    fn test_pre_exec_hooks_are_skipped() {
+        let mut cmd = command("sleep");
+        cmd.arg("1");
+        cmd.cwd("/tmp");
+        cmd.umask(Some(0o027));
+        cmd.set_controlling_tty(false);
+        cmd.set_kill_policy(KillPolicy::new());
+        cmd.uid(Some(1000));
+        cmd.gid(Some(1000));
+        cmd.groups(Some(vec![1000, 1001]));
+        cmd.init_groups(Some("nobody".to_string()));
+        cmd.rlimit(Resource::NoFile, 256, 1024);
+        let without_hooks = cmd.clone();
+        unsafe {
+            cmd.pre_exec(|| Ok(()));
+        }
+        assert_ne!(cmd, without_hooks);
+
+        // The hooks are dropped and everything else survives
+        assert_eq!(round_trip(&cmd), without_hooks);
+    }
+
+    #[test]
+    fn test_exit_status() {
+        let status: ExitStatus = std::process::Command::new("sh")
+            .args(["-c", "kill -KILL $$"])
+            .status()
+            .unwrap()
+            .into();
+        let copy: ExitStatus =
+            serde_json::from_str(&serde_json::to_string(&status).unwrap()).unwrap();
+        assert_eq!(copy.signal_number(), Some(libc::SIGKILL));
+        assert_eq!(copy.raw_status(), status.raw_status());
+        assert_eq!(copy.signal(), status.signal());
+
+        // Statuses serialized before the signal details were recorded
+        let old: ExitStatus = serde_json::from_str(r#"{"code":1,"signal":"Killed"}"#).unwrap();
+        assert_eq!(old.exit_code(), 1);
+        assert_eq!(old.signal(), Some("Killed"));
+        assert_eq!(old.signal_number(), None);
+        assert!(!old.core_dumped());
+        assert_eq!(old.raw_status(), None);
+    }
+}
@@ -0,0 +1,88 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::unix::Signal;
+    use portable_pty::{CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
+    use std::io::Read;
+
+    #[test]
+    #[timeout(5000)]
+    fn test_signal_from_cloned_killer_while_waiting() {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master: _master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sleep");
+        cmd.arg("10");
+        let mut child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let mut killer = child.clone_killer();
+        let waiter = std::thread::spawn(move || child.wait());
+
+        killer.signal(Signal::SIGTERM).unwrap();
+
+        let status = waiter.join().unwrap().unwrap();
+        assert!(!status.success());
+        assert_eq!(status.signal(), Some("Terminated"), "status: {}", status);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_signal_handled_by_child() {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        // The trap is installed before `ready` is printed, so once we have
+        // seen that in the output it is safe to deliver the signal
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args([
+            "-c",
+            "trap 'echo got-usr1; exit 7' USR1; echo ready; while :; do sleep 0.05; done",
+        ]);
+        let mut child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let mut reader = master.try_clone_reader().unwrap();
+        let mut output = String::new();
+        let mut buffer = [0u8; 1024];
+        while !output.contains("ready") {
+            let n = reader.read(&mut buffer).unwrap();
+            assert!(n > 0, "Unexpected EOF, output was: {:?}", output);
+            output.push_str(&String::from_utf8_lossy(&buffer[..n]));
+        }
+
+        child.signal(Signal::SIGUSR1).unwrap();
+
+        let status = child.wait().unwrap();
+        assert_eq!(status.exit_code(), 7);
+
+        reader.read_to_string(&mut output).unwrap();
+        assert!(output.contains("got-usr1"), "Output was: {:?}", output);
+
+        // Signalling a child that has already been reaped is an error
+        assert!(child.signal(Signal::SIGUSR1).is_err());
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_cloned_killer_after_reap() {
+        let pty_system = NativePtySystem::default();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sleep");
+        cmd.arg("10");
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+
+        let mut killer = child.clone_killer();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // Once the child is gone, the cloned killer must not signal
+        // whatever process may since have been given the same pid
+        let err = killer.signal(Signal::SIGTERM).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ESRCH));
+        // ...while killing it is trivially successful
+        killer.kill().unwrap();
+    }
+}
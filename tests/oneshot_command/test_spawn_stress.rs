@@ -0,0 +1,83 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::run::{RunOptions, run};
+    use portable_pty::{CommandBuilder, PtySize, native_pty_system};
+    use std::sync::Arc;
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const SPAWNING_THREADS: usize = 12;
+    const ALLOCATING_THREADS: usize = 4;
+    const SPAWNS_PER_THREAD: usize = 10;
+
+    #[test]
+    #[timeout(60000)]
+    fn test_spawn_from_many_threads() {
+        // Forking while other threads hold the allocator lock deadlocks
+        // a child that allocates before exec, so keep the allocator busy
+        // while spawning from many threads at once
+        let done = Arc::new(AtomicBool::new(false));
+        let allocators: Vec<_> = (0..ALLOCATING_THREADS)
+            .map(|_| {
+                let done = done.clone();
+                std::thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        let chunks: Vec<Vec<u8>> = (1..64).map(|n| vec![0u8; n * 64]).collect();
+                        std::hint::black_box(chunks);
+                    }
+                })
+            })
+            .collect();
+
+        let barrier = Arc::new(Barrier::new(SPAWNING_THREADS));
+        let spawners: Vec<_> = (0..SPAWNING_THREADS)
+            .map(|_| {
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    for _ in 0..SPAWNS_PER_THREAD {
+                        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+                        let mut child = pair
+                            .slave
+                            .spawn_command(CommandBuilder::new("true"))
+                            .unwrap();
+                        drop(pair.slave);
+                        let status = child.wait().unwrap();
+                        assert!(status.success(), "child exited with {status}");
+                    }
+                })
+            })
+            .collect();
+
+        for spawner in spawners {
+            spawner.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        for allocator in allocators {
+            allocator.join().unwrap();
+        }
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_fds_are_not_leaked() {
+        // Without close-on-exec, this would be inherited by the child
+        // if the spawn didn't close it
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [read_fd, write_fd] = fds;
+
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args([
+            "-c",
+            &format!("test -e /dev/fd/{read_fd} || test -e /dev/fd/{write_fd}"),
+        ]);
+        let result = run(cmd, &RunOptions::new()).unwrap();
+        unsafe {
+            libc::close(read_fd);
+            libc::close(write_fd);
+        }
+        assert!(!result.status.success());
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::unix::{Signal, StatusChange, UnixChild};
+    use portable_pty::{
+        Child, ChildKiller, CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem,
+    };
+
+    #[test]
+    #[timeout(5000)]
+    fn test_stopped_and_continued() {
+        let pty_system = NativePtySystem::default();
+        let PtyPair { master: _master, slave } = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sleep");
+        cmd.arg("10");
+        let mut child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let child: &mut dyn Child = &mut *child;
+        let child = child.downcast_mut::<UnixChild>().unwrap();
+        assert!(child.try_wait_status_change().unwrap().is_none());
+
+        // The child leads a process group that is orphaned from the point
+        // of view of its session, so the kernel would discard SIGTSTP
+        child.signal(Signal::SIGSTOP).unwrap();
+        match child.wait_status_change().unwrap() {
+            StatusChange::Stopped(signal) => assert_eq!(signal, Signal::SIGSTOP),
+            change => panic!("expected the child to stop, got {:?}", change),
+        }
+        // Being stopped isn't termination
+        assert!(child.try_wait().unwrap().is_none());
+
+        child.signal(Signal::SIGCONT).unwrap();
+        match child.wait_status_change().unwrap() {
+            StatusChange::Continued => {}
+            change => panic!("expected the child to continue, got {:?}", change),
+        }
+
+        child.signal(Signal::SIGKILL).unwrap();
+        match child.wait_status_change().unwrap() {
+            StatusChange::Exited(status) => assert_eq!(status.signal_number(), Some(9)),
+            change => panic!("expected the child to exit, got {:?}", change),
+        }
+        assert!(child.try_wait().unwrap().is_some());
+    }
+}
@@ -26,7 +26,7 @@ mod tests {
             barrier_clone.wait();
 
             std::thread::sleep(std::time::Duration::from_millis(500));
-            tx_debug_info_clone.send(format!("waiting for child process to exit")).unwrap();
+            tx_debug_info_clone.send("waiting for child process to exit".to_string()).unwrap();
             let wait_result = child.wait();
 
             let _ = tx_exit_status.send(wait_result);
@@ -34,10 +34,10 @@ mod tests {
 
         barrier.wait();
 
-        tx_debug_info.send(format!("killing child process")).unwrap();
+        tx_debug_info.send("killing child process".to_string()).unwrap();
         let _kill_result = killer.kill();
 
-        tx_debug_info.send(format!("waiting for exit status")).unwrap();
+        tx_debug_info.send("waiting for exit status".to_string()).unwrap();
         let wait_result = rx_exit_status.recv().map_err(|e| e.to_string())?;
         tx_debug_info.send(format!("wait_result = {:?}", wait_result)).unwrap();
 
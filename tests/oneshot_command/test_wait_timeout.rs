@@ -0,0 +1,67 @@
+#[cfg(all(test, unix))]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[timeout(5000)]
+    fn test_wait_timeout() {
+        let pty_system = NativePtySystem::default();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sleep");
+        cmd.arg("10");
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+
+        let start = Instant::now();
+        assert!(child
+            .wait_timeout(Duration::from_millis(100))
+            .unwrap()
+            .is_none());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        child.kill().unwrap();
+        let start = Instant::now();
+        let status = child.wait_timeout(Duration::from_secs(3)).unwrap().unwrap();
+        assert!(!status.success());
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_wait_deadline() {
+        let pty_system = NativePtySystem::default();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "sleep 0.1; exit 4"]);
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+
+        let deadline = Instant::now() + Duration::from_secs(3);
+        let status = child.wait_deadline(deadline).unwrap().unwrap();
+        assert_eq!(status.exit_code(), 4);
+
+        // A deadline in the past still reports a status that is available
+        let status = child.wait_deadline(Instant::now()).unwrap().unwrap();
+        assert_eq!(status.exit_code(), 4);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_wait_timeout_max() {
+        let pty_system = NativePtySystem::default();
+        let pair = pty_system.openpty(PtySize::default()).unwrap();
+
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "exit 3"]);
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+
+        // Too large for a deadline, so this waits indefinitely
+        let status = child.wait_timeout(Duration::MAX).unwrap().unwrap();
+        assert_eq!(status.exit_code(), 3);
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use portable_pty::asciicast::{Header, RecordingMasterPty};
+    use portable_pty::{CommandBuilder, MasterPty, PtySize};
+    use std::sync::{Arc, Mutex};
+
+    /// An output that can be inspected after being handed to the recorder
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let mut cmd = CommandBuilder::new("echo");
+        cmd.args(["hello world", "\"quoted\""]);
+        cmd.env("TERM", "xterm-256color");
+
+        let mut header = Header::from_command(PtySize::default(), &cmd);
+        header.timestamp = Some(1700000000);
+        header.env.retain(|(key, _)| key == "TERM");
+        assert_eq!(
+            header.to_json(),
+            concat!(
+                r#"{"version": 2, "width": 80, "height": 24, "timestamp": 1700000000, "#,
+                r#""command": "echo 'hello world' '\"quoted\"'", "#,
+                r#""env": {"TERM": "xterm-256color"}}"#
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[ntest::timeout(5000)]
+    fn test_record_session() {
+        use portable_pty::{NativePtySystem, PtyPair, PtySystem};
+        use std::io::{Read, Write};
+
+        let size = PtySize::default();
+        let PtyPair { master, slave } = NativePtySystem::default().openpty(size).unwrap();
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "read line; echo \"got $line\""]);
+        let header = Header::from_command(size, &cmd);
+        let mut child = slave.spawn_command(cmd).unwrap();
+        drop(slave);
+
+        let output = SharedBuffer::default();
+        let master = RecordingMasterPty::new(master, header, output.clone()).unwrap();
+        let mut reader = master.try_clone_reader().unwrap();
+        let mut writer = master.take_writer().unwrap();
+
+        master
+            .resize(PtySize {
+                rows: 30,
+                cols: 100,
+                ..size
+            })
+            .unwrap();
+        writer.write_all(b"caf\xc3").unwrap();
+        writer.write_all(b"\xa9\n").unwrap();
+
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        child.wait().unwrap();
+        assert!(text.contains("got café"), "output: {:?}", text);
+
+        let lines = output.lines();
+        assert!(lines[0].starts_with(r#"{"version": 2, "width": 80, "height": 24"#));
+        assert!(lines[0].contains(r#""command": "sh -c 'read line; echo "#));
+
+        let events = &lines[1..];
+        assert!(events[0].ends_with(r#", "r", "100x30"]"#), "{:?}", events);
+
+        // Returns the concatenated data of the events of the given type
+        let data = |code: &str| -> String {
+            let marker = format!(", \"{code}\", \"");
+            events
+                .iter()
+                .filter_map(|e| Some(e.split_once(&marker)?.1.strip_suffix("\"]")?.to_string()))
+                .collect()
+        };
+        // The character split across writes isn't mangled
+        assert_eq!(data("i"), "café\\n");
+        let recorded_output = data("o");
+        assert!(recorded_output.contains("got café"), "{:?}", events);
+    }
+}
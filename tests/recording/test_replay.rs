@@ -0,0 +1,162 @@
+#[cfg(test)]
+mod tests {
+    use ntest::timeout;
+    use portable_pty::replay::{Pacing, Recording, ReplayPtySystem};
+    use portable_pty::{CommandBuilder, ExitStatus, PtySize, PtySystem};
+    use std::io::Read;
+    use std::time::Duration;
+
+    #[cfg(feature = "asciicast")]
+    const CAST: &str = concat!(
+        "{\"version\": 2, \"width\": 100, \"height\": 30}\n",
+        "[0.0, \"o\", \"hello \"]\n",
+        "[0.1, \"i\", \"ignored\"]\n",
+        "[0.2, \"r\", \"120x40\"]\n",
+        "[0.3, \"o\", \"world\\r\\n\"]\n",
+        "[0.3, \"x\", \"3\"]\n",
+    );
+
+    fn replay(recording: Recording, pacing: Pacing) -> (String, ExitStatus, PtySize) {
+        let size = recording.size();
+        let pair = ReplayPtySystem::new(recording, pacing)
+            .openpty(size)
+            .unwrap();
+        let mut child = pair
+            .slave
+            .spawn_command(CommandBuilder::new_default_prog())
+            .unwrap();
+        let mut output = String::new();
+        pair.master
+            .try_clone_reader()
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        let status = child.wait().unwrap();
+        (output, status, pair.master.get_size().unwrap())
+    }
+
+    #[cfg(feature = "asciicast")]
+    #[test]
+    #[timeout(5000)]
+    fn test_replay_asciicast() {
+        let recording = Recording::from_asciicast(CAST.as_bytes()).unwrap();
+        assert_eq!(recording.size().cols, 100);
+        assert_eq!(recording.size().rows, 30);
+        assert_eq!(recording.duration(), Duration::from_millis(300));
+
+        let (output, status, size) = replay(recording, Pacing::Immediate);
+        assert_eq!(output, "hello world\r\n");
+        assert_eq!(status.exit_code(), 3);
+        assert_eq!((size.cols, size.rows), (120, 40));
+    }
+
+    #[cfg(feature = "asciicast")]
+    #[test]
+    #[timeout(5000)]
+    fn test_replay_pacing() {
+        use std::time::Instant;
+
+        let recording = Recording::from_asciicast(CAST.as_bytes()).unwrap();
+        let start = Instant::now();
+        replay(recording, Pacing::Accelerated(2.0));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(300), "{:?}", elapsed);
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_replay_kill() {
+        let recording = Recording::from_script("60.0 4\n".as_bytes(), "late".as_bytes()).unwrap();
+        let pair = ReplayPtySystem::new(recording, Pacing::RealTime)
+            .openpty(PtySize::default())
+            .unwrap();
+        let mut child = pair
+            .slave
+            .spawn_command(CommandBuilder::new_default_prog())
+            .unwrap();
+        assert!(child.try_wait().unwrap().is_none());
+        assert!(
+            pair.slave
+                .spawn_command(CommandBuilder::new_default_prog())
+                .is_err()
+        );
+
+        child.clone_killer().kill().unwrap();
+        assert!(!child.wait().unwrap().success());
+        let mut output = String::new();
+        pair.master
+            .try_clone_reader()
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_replay_kill_unread() {
+        // Far more output than the pty can buffer, which nobody reads
+        let typescript = "x".repeat(16 * 1024 * 1024);
+        let timing = format!("0.0 {}\n", typescript.len());
+        let recording = Recording::from_script(timing.as_bytes(), typescript.as_bytes()).unwrap();
+        let pair = ReplayPtySystem::new(recording, Pacing::Immediate)
+            .openpty(PtySize::default())
+            .unwrap();
+        let mut child = pair
+            .slave
+            .spawn_command(CommandBuilder::new_default_prog())
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(child.try_wait().unwrap().is_none());
+
+        child.kill().unwrap();
+        assert!(!child.wait().unwrap().success());
+    }
+
+    #[test]
+    #[timeout(5000)]
+    fn test_replay_script() {
+        let typescript = "Script started on 2024-01-01 00:00:00\nabcdefgh";
+        let classic = "0.000001 3\n0.1 5\n";
+        let recording = Recording::from_script(classic.as_bytes(), typescript.as_bytes()).unwrap();
+        assert_eq!(recording.duration(), Duration::from_micros(100_001));
+        let (output, status, _) = replay(recording, Pacing::Immediate);
+        assert_eq!(output, "abcdefgh");
+        assert!(status.success());
+
+        let advanced = "H 0.0 TERM xterm\nO 0.1 4\nI 0.2 1\nO 0.0 4\n";
+        let mut recording =
+            Recording::from_script(advanced.as_bytes(), "abcdefgh".as_bytes()).unwrap();
+        recording.set_exit_status(ExitStatus::with_exit_code(2));
+        let (output, status, _) = replay(recording, Pacing::Immediate);
+        assert_eq!(output, "abcdefgh");
+        assert_eq!(status.exit_code(), 2);
+
+        assert!(Recording::from_script("0.1 20\n".as_bytes(), "short".as_bytes()).is_err());
+    }
+
+    #[cfg(feature = "asciicast")]
+    #[test]
+    fn test_read_asciicast() {
+        use portable_pty::asciicast::read_asciicast;
+
+        let cast = concat!(
+            "{\"version\": 2, \"width\": 80, \"height\": 24, \"env\": {\"TERM\": \"xterm\"}}\n",
+            "[0.5, \"o\", \"\\ud83d\\ude00\\u00e9\\n\\\\\"]\n",
+        );
+        let (header, events) = read_asciicast(cast.as_bytes()).unwrap();
+        assert_eq!((header.width, header.height), (80, 24));
+        assert_eq!(header.env, [("TERM".to_string(), "xterm".to_string())]);
+        assert_eq!(events[0].time, Duration::from_millis(500));
+        assert_eq!(events[0].data, "\u{1f600}\u{e9}\n\\");
+
+        let header = "{\"version\": 2, \"width\": 80, \"height\": 24}\n";
+        // Deeply nested input is rejected rather than overflowing the stack
+        let nested = format!("{header}{}{}\n", "[".repeat(100_000), "]".repeat(100_000));
+        assert!(read_asciicast(nested.as_bytes()).is_err());
+        // Exit codes can't be negative
+        let negative = format!("{header}[0.0, \"x\", \"-1\"]\n");
+        assert!(Recording::from_asciicast(negative.as_bytes()).is_err());
+    }
+}
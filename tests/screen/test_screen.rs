@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use portable_pty::PtySize;
+    use portable_pty::screen::{Color, CursorPosition, Screen};
+
+    fn size(rows: u16, cols: u16) -> PtySize {
+        PtySize {
+            rows,
+            cols,
+            ..PtySize::default()
+        }
+    }
+
+    #[test]
+    fn test_cursor_movement_and_erase() {
+        let mut screen = Screen::new(size(5, 20));
+        screen.process(b"first line\r\nsecond line");
+        assert_eq!(screen.cursor(), CursorPosition { row: 1, col: 11 });
+
+        // Overwrite "second" with "SECOND" and erase the rest of the line
+        screen.process(b"\x1b[2;1HSECOND\x1b[K");
+        assert_eq!(screen.contents(), "first line\nSECOND");
+
+        // Insert and delete characters
+        screen.process(b"\x1b[1;1H\x1b[2@>>\x1b[1;8H\x1b[2P");
+        assert_eq!(screen.row_text(0), ">>firstine");
+
+        screen.process(b"\x1b[2J");
+        assert_eq!(screen.contents(), "");
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut screen = Screen::new(size(5, 20));
+        screen.process(b"\x1b[1;31mA\x1b[0;4;38;5;200mB\x1b[38;2;1;2;3;48;5;7mC\x1b[mD");
+
+        let a = screen.cell(0, 0).unwrap();
+        assert_eq!(a.ch, 'A');
+        assert!(a.attributes.bold);
+        assert_eq!(a.attributes.foreground, Color::Indexed(1));
+
+        let b = screen.cell(0, 1).unwrap();
+        assert!(!b.attributes.bold);
+        assert!(b.attributes.underline);
+        assert_eq!(b.attributes.foreground, Color::Indexed(200));
+
+        let c = screen.cell(0, 2).unwrap();
+        assert_eq!(c.attributes.foreground, Color::Rgb(1, 2, 3));
+        assert_eq!(c.attributes.background, Color::Indexed(7));
+
+        let d = screen.cell(0, 3).unwrap();
+        assert_eq!(d.attributes, Default::default());
+    }
+
+    #[test]
+    fn test_wrap_and_scroll() {
+        let mut screen = Screen::new(size(3, 5));
+        screen.process(b"abcdefgh\r\nline3\r\nline4");
+        assert_eq!(screen.contents(), "fgh\nline3\nline4");
+
+        // With a scroll region, only the rows inside it scroll
+        let mut screen = Screen::new(size(4, 10));
+        screen.process(b"header\x1b[2;4r\x1b[2;1Ha\r\nb\r\nc\r\nd");
+        assert_eq!(screen.contents(), "header\nb\nc\nd");
+    }
+
+    #[test]
+    fn test_alternate_screen_and_title() {
+        let mut screen = Screen::new(size(5, 20));
+        screen.process(b"\x1b]0;my title\x07shell$ ");
+        assert_eq!(screen.title(), "my title");
+
+        screen.process(b"\x1b[?1049h\x1b[?25l\x1b[Hfull screen app");
+        assert!(screen.alternate_screen());
+        assert!(!screen.cursor_visible());
+        assert_eq!(screen.contents(), "full screen app");
+
+        screen.process(b"\x1b[?1049l\x1b[?25h");
+        assert!(!screen.alternate_screen());
+        assert_eq!(screen.contents(), "shell$");
+        assert_eq!(screen.cursor(), CursorPosition { row: 0, col: 7 });
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut screen = Screen::new(size(5, 20));
+        screen.process(b"0123456789\x1b[5;15H");
+
+        screen.resize(size(3, 5));
+        assert_eq!(screen.size(), size(3, 5));
+        assert_eq!(screen.contents(), "01234");
+        assert_eq!(screen.cursor(), CursorPosition { row: 2, col: 4 });
+
+        screen.resize(size(4, 8));
+        screen.process(b"\x1b[4;8HX");
+        assert_eq!(screen.row_text(3), "       X");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[ntest::timeout(5000)]
+    fn test_pty_output() {
+        use portable_pty::{CommandBuilder, NativePtySystem, PtySystem};
+
+        let pty_system = NativePtySystem::default();
+        let pair = pty_system.openpty(size(10, 40)).unwrap();
+
+        // A redraw in the style of a progress indicator
+        let mut cmd = CommandBuilder::new("printf");
+        cmd.arg("progress: 10%%\\rprogress: 100%%\\n\\033[1mdone\\033[0m\\n");
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let mut screen = Screen::new(size(10, 40));
+        std::io::copy(&mut reader, &mut screen).unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(screen.contents(), "progress: 100%\ndone");
+        assert!(screen.cell(1, 0).unwrap().attributes.bold);
+    }
+}